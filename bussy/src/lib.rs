@@ -42,6 +42,10 @@
 //! # }
 //! ```
 
+pub use {
+    enumflags2::BitFlags,
+    zbus::fdo::{RequestNameFlags, RequestNameReply},
+};
 use {
     error_reporter::Report,
     parking_lot::Mutex,
@@ -61,6 +65,7 @@ use {
             Arc, Weak,
         },
         task::{Context, Poll},
+        time::Duration,
     },
     thiserror::Error,
     tokio::{
@@ -68,7 +73,7 @@ use {
             mpsc::{self, UnboundedReceiver},
             oneshot,
         },
-        task::JoinHandle,
+        task::{AbortHandle, JoinHandle},
     },
     zbus::{
         export::futures_util::StreamExt,
@@ -133,10 +138,25 @@ struct SignalHandlerData<T: ?Sized> {
 }
 
 type MethodReplyHandler = Box<dyn FnOnce(Result<Message, Error>) + Send>;
+
+struct PendingCall {
+    handler: MethodReplyHandler,
+    timeout: Option<AbortHandle>,
+}
 type DynSignalHandler = Arc<SignalHandlerData<dyn Fn(&Message) + Send + Sync>>;
 
+impl PendingCall {
+    fn complete(self, res: Result<Message, Error>) {
+        if let Some(task) = self.timeout {
+            task.abort();
+        }
+        (self.handler)(res);
+    }
+}
+
 struct SharedMut {
-    pending_replies: HashMap<NonZeroU32, MethodReplyHandler>,
+    pending_replies: HashMap<NonZeroU32, PendingCall>,
+    default_timeout: Option<Duration>,
     objects: HashMap<ObjectPath<'static>, Arc<ObjectData>>,
     weak_objects: HashMap<ObjectPath<'static>, Weak<Object>>,
     signal_handlers: HashMap<usize, DynSignalHandler>,
@@ -175,6 +195,9 @@ pub enum Error {
     /// Could not map a property value to the desired type.
     #[error("Could not map a property value to the desired type")]
     MapProperty(#[source] Box<dyn StdError + Sync + Send>),
+    /// The peer did not reply in time.
+    #[error("The method call timed out")]
+    Timeout,
 }
 
 const DBUS_INTROSPECTABLE_NAME: InterfaceName<'static> =
@@ -189,6 +212,8 @@ const DBUS_PATH: ObjectPath<'static> =
     ObjectPath::from_static_str_unchecked("/org/freedesktop/DBus");
 const NAME_OWNER_CHANGED: MemberName<'static> =
     MemberName::from_static_str_unchecked("NameOwnerChanged");
+const NAME_ACQUIRED: MemberName<'static> = MemberName::from_static_str_unchecked("NameAcquired");
+const NAME_LOST: MemberName<'static> = MemberName::from_static_str_unchecked("NameLost");
 const REQUEST_NAME: MemberName<'static> = MemberName::from_static_str_unchecked("RequestName");
 const GET: MemberName<'static> = MemberName::from_static_str_unchecked("Get");
 const GET_ALL: MemberName<'static> = MemberName::from_static_str_unchecked("GetAll");
@@ -218,7 +243,7 @@ impl Shared {
             mem::take(&mut shared.pending_replies)
         };
         for (_, pending) in pending {
-            pending(Err(Error::Killed));
+            pending.complete(Err(Error::Killed));
         }
     }
 
@@ -322,20 +347,20 @@ impl Shared {
                     if msg.message_type() == Type::Error {
                         'handle_error: {
                             let Some(name) = header.error_name() else {
-                                pending(Err(Error::NoErrorName));
+                                pending.complete(Err(Error::NoErrorName));
                                 break 'handle_error;
                             };
                             let s = match msg.body().deserialize::<String>() {
                                 Ok(s) => s,
                                 Err(e) => {
-                                    pending(Err(Error::NoErrorBody(e)));
+                                    pending.complete(Err(Error::NoErrorBody(e)));
                                     break 'handle_error;
                                 }
                             };
-                            pending(Err(Error::ErrorReply(name.to_string(), s)));
+                            pending.complete(Err(Error::ErrorReply(name.to_string(), s)));
                         }
                     } else {
-                        pending(Ok(msg));
+                        pending.complete(Ok(msg));
                     }
                 }
                 Type::Signal => {
@@ -453,7 +478,32 @@ impl Shared {
         else {
             return;
         };
-        pending(Err(e));
+        pending.complete(Err(e));
+    }
+
+    fn set_timeout(self: &Arc<Self>, serial: NonZeroU32, timeout: Option<Duration>) {
+        let mut shared = self.shared.lock();
+        let Some(pending) = shared.pending_replies.get_mut(&serial) else {
+            return;
+        };
+        if let Some(task) = pending.timeout.take() {
+            task.abort();
+        }
+        let Some(timeout) = timeout else {
+            return;
+        };
+        let slf = Arc::downgrade(self);
+        let task = tokio::spawn(async move {
+            tokio::time::sleep(timeout).await;
+            let Some(slf) = slf.upgrade() else {
+                return;
+            };
+            let pending = slf.shared.lock().pending_replies.remove(&serial);
+            if let Some(pending) = pending {
+                (pending.handler)(Err(Error::Timeout));
+            }
+        });
+        pending.timeout = Some(task.abort_handle());
     }
 
     async fn kill_queue(self: Arc<Self>, mut queue: UnboundedReceiver<Message>) {
@@ -533,9 +583,17 @@ impl Shared {
             });
             callback(msg);
         });
-        {
+        let timeout = {
             let mut shared = self.shared.lock();
-            shared.pending_replies.insert(serial, callback);
+            let pending = PendingCall {
+                handler: callback,
+                timeout: None,
+            };
+            shared.pending_replies.insert(serial, pending);
+            shared.default_timeout
+        };
+        if timeout.is_some() {
+            self.set_timeout(serial, timeout);
         }
         let _ = if self.killed.load(Relaxed) {
             kill_queue.send(message)
@@ -654,14 +712,20 @@ impl Shared {
         obj
     }
 
-    fn request_name(&self, name: WellKnownName<'_>) {
-        self.call_no_reply(
+    fn request_name(
+        self: &Arc<Self>,
+        name: WellKnownName<'_>,
+        flags: BitFlags<RequestNameFlags>,
+        kill_queue: &mpsc::UnboundedSender<Message>,
+    ) -> CallFuture<RequestNameReply> {
+        self.call(
             DBUS_NAME.into(),
             DBUS_INTERFACE,
             DBUS_PATH,
             REQUEST_NAME,
-            &(name.as_str(), 0u32),
-        );
+            kill_queue,
+            &(name.as_str(), flags),
+        )
     }
 
     fn get_property_async<CB, R>(
@@ -726,6 +790,7 @@ impl Connection {
         let shared = Arc::new(Shared {
             shared: Mutex::new(SharedMut {
                 pending_replies: Default::default(),
+                default_timeout: None,
                 objects: Default::default(),
                 weak_objects: Default::default(),
                 signal_handlers: Default::default(),
@@ -753,6 +818,15 @@ impl Connection {
         }
     }
 
+    /// Sets the timeout of method calls made after this function returns.
+    ///
+    /// If no reply arrives in time, the call fails with [Error::Timeout]. `None`, the
+    /// default, means that calls wait for a reply forever. Individual calls can override
+    /// this with [Call::set_timeout].
+    pub fn set_default_timeout(&self, timeout: Option<Duration>) {
+        self.shared.shared.lock().default_timeout = timeout;
+    }
+
     /// Sends a signal.
     pub fn send_signal<'a>(
         &self,
@@ -904,9 +978,36 @@ impl Connection {
         )
     }
 
+    /// Invokes the callback whenever this connection acquires a name.
+    ///
+    /// This is a convenience method around [Self::handle_signal]. See that method for
+    /// more details.
+    pub fn on_name_acquired(&self, f: impl Fn(String) + Send + Sync + 'static) -> SignalHandler {
+        self.handle_signal(DBUS_INTERFACE, NAME_ACQUIRED, move |(name,): (String,)| {
+            f(name)
+        })
+    }
+
+    /// Invokes the callback whenever this connection loses a name.
+    ///
+    /// This is a convenience method around [Self::handle_signal]. See that method for
+    /// more details.
+    pub fn on_name_lost(&self, f: impl Fn(String) + Send + Sync + 'static) -> SignalHandler {
+        self.handle_signal(DBUS_INTERFACE, NAME_LOST, move |(name,): (String,)| f(name))
+    }
+
     /// Requests a name.
-    pub fn request_name<'a>(&self, name: impl Into<WellKnownName<'a>>) {
-        self.shared.request_name(name.into())
+    ///
+    /// The request is sent immediately. The returned future can be used to await the
+    /// reply of the message bus but it does not have to be awaited. If the name is
+    /// queued, [Self::on_name_acquired] can be used to learn when it is acquired.
+    pub fn request_name<'a>(
+        &self,
+        name: impl Into<WellKnownName<'a>>,
+        flags: BitFlags<RequestNameFlags>,
+    ) -> CallFuture<RequestNameReply> {
+        self.shared
+            .request_name(name.into(), flags, &self.kill_queue)
     }
 
     /// Retrieves a property and waits for the reply with a callback.
@@ -1136,16 +1237,29 @@ impl Call {
     pub fn detach(&mut self) {
         self.detached = true;
     }
+
+    /// Sets the timeout of this call.
+    ///
+    /// The timeout starts when this function is called and replaces the default timeout
+    /// of the connection. If no reply arrives in time, the call fails with
+    /// [Error::Timeout]. `None` disables the timeout.
+    pub fn set_timeout(&self, timeout: Option<Duration>) {
+        self.shared.set_timeout(self.serial, timeout);
+    }
 }
 
 impl Drop for Call {
     fn drop(&mut self) {
         if !self.detached {
-            self.shared
+            let pending = self
+                .shared
                 .shared
                 .lock()
                 .pending_replies
                 .remove(&self.serial);
+            if let Some(task) = pending.and_then(|p| p.timeout) {
+                task.abort();
+            }
         }
     }
 }
@@ -1158,6 +1272,16 @@ pub struct CallFuture<T> {
     recv: oneshot::Receiver<Result<T, Error>>,
 }
 
+impl<T> CallFuture<T> {
+    /// Sets the timeout of this call.
+    ///
+    /// See [Call::set_timeout].
+    pub fn with_timeout(self, timeout: Option<Duration>) -> Self {
+        self.call.set_timeout(timeout);
+        self
+    }
+}
+
 impl<T> Future for CallFuture<T> {
     type Output = Result<T, Error>;
 
@@ -1,9 +1,16 @@
 use {
-    crate::{settings, wayland},
-    clap::Parser,
+    crate::{settings, sni, wayland},
+    bussy::ConnectionHolder,
+    clap::{Args, CommandFactory, Parser, Subcommand},
+    clap_complete::Shell,
     error_reporter::Report,
+    std::io,
+    thiserror::Error,
 };
 
+mod client;
+mod inspect;
+
 /// wl-tray-bridge.
 ///
 /// Creates a bridge between applications implementing the StatusNotifierItem protocol
@@ -13,16 +20,155 @@ struct Cli {
     /// Path to the config file.
     ///
     /// Defaults to `~/.config/wl-tray-bridge/config.toml`.
-    #[clap(long)]
+    #[clap(long, global = true)]
     config: Option<String>,
+    /// Check the config file for errors and exit.
+    ///
+    /// Exits with a non-zero status if the config file contains errors.
+    #[clap(long)]
+    check_config: bool,
+    /// Print the effective config, including all defaults, and exit.
+    #[clap(long)]
+    print_config: bool,
+    /// Only act as the StatusNotifierWatcher and do not display any items.
+    ///
+    /// This does not require a compositor.
+    #[clap(long, conflicts_with = "host_only")]
+    watcher_only: bool,
+    /// Never act as the StatusNotifierWatcher.
+    ///
+    /// By default, the bridge acts as the watcher unless another watcher is already
+    /// running, and takes over once that watcher exits.
+    #[clap(long)]
+    host_only: bool,
+    #[clap(subcommand)]
+    cmd: Option<Cmd>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Cmd {
+    /// Run the bridge.
+    ///
+    /// This is the default if no subcommand is given.
+    Run,
+    /// List the items known to the running bridge.
+    List,
+    /// Activate an item of the running bridge.
+    Activate(ItemArgs),
+    /// Open the menu of an item of the running bridge.
+    Menu(ItemArgs),
+    /// Make the running bridge reload its config file.
+    Reload,
+    /// Print the properties and the menu of a StatusNotifierItem.
+    ///
+    /// This does not require the bridge or a compositor to be running.
+    Inspect(InspectArgs),
+    /// Print shell completions to stdout.
+    Completions(CompletionsArgs),
+}
+
+#[derive(Args, Debug)]
+struct ItemArgs {
+    /// The SNI id or the bus name of the item.
+    id: String,
+}
+
+#[derive(Args, Debug)]
+struct InspectArgs {
+    /// The bus name of the item, optionally followed by its object path.
+    ///
+    /// For example `:1.42` or `:1.42/org/ayatana/NotificationItem/foo`. The path
+    /// defaults to `/StatusNotifierItem`.
+    bus_name: String,
+    /// Print JSON instead of a tree.
+    #[clap(long)]
+    json: bool,
+}
+
+#[derive(Args, Debug)]
+struct CompletionsArgs {
+    /// The shell to generate completions for.
+    shell: Shell,
+}
+
+#[derive(Debug, Error)]
+enum CliError {
+    #[error("Could not connect to dbus")]
+    ConnectDbus(#[source] zbus::Error),
+    #[error("The bridge could not handle the request")]
+    Control(#[source] bussy::Error),
+    #[error("{0:?} is not a valid bus name")]
+    InvalidBusName(String),
+    #[error("{0:?} is not a valid object path")]
+    InvalidPath(String),
+    #[error("Could not retrieve the properties of the item")]
+    GetProperties(#[source] bussy::Error),
+    #[error("Could not retrieve the menu of the item")]
+    GetLayout(#[source] bussy::Error),
+    #[error("Could not write to stdout")]
+    Stdout(#[source] io::Error),
+    #[error("Could not load the config")]
+    LoadConfig(#[source] settings::LoadError),
+    #[error("The config contains errors")]
+    InvalidConfig,
 }
 
 pub async fn run() {
     let cli = Cli::parse();
 
-    settings::init(cli.config.as_deref());
+    if cli.check_config || cli.print_config {
+        let res = check_config(cli.config.as_deref(), cli.check_config, cli.print_config);
+        if let Err(e) = res {
+            log::error!("{}", Report::new(e));
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let res = match cli.cmd.unwrap_or(Cmd::Run) {
+        Cmd::Run if cli.watcher_only => {
+            let Err(e) = sni::run_watcher().await;
+            log::error!("A fatal error occurred: {}", Report::new(e));
+            std::process::exit(1);
+        }
+        Cmd::Run => {
+            settings::init(cli.config.as_deref());
+            let Err(e) = wayland::run(!cli.host_only).await;
+            log::error!("A fatal error occurred: {}", Report::new(e));
+            std::process::exit(1);
+        }
+        Cmd::List => client::list().await,
+        Cmd::Activate(a) => client::activate(&a.id).await,
+        Cmd::Menu(a) => client::open_menu(&a.id).await,
+        Cmd::Reload => client::reload().await,
+        Cmd::Inspect(a) => inspect::run(&a.bus_name, a.json).await,
+        Cmd::Completions(a) => {
+            let mut cmd = Cli::command();
+            let name = cmd.get_name().to_string();
+            clap_complete::generate(a.shell, &mut cmd, name, &mut io::stdout());
+            Ok(())
+        }
+    };
+    if let Err(e) = res {
+        log::error!("{}", Report::new(e));
+        std::process::exit(1);
+    }
+}
+
+fn check_config(config: Option<&str>, check: bool, print: bool) -> Result<(), CliError> {
+    let effective = settings::load_effective(config).map_err(CliError::LoadConfig)?;
+    for e in &effective.errors {
+        eprintln!("{}: {e}", effective.path);
+    }
+    if print {
+        print!("{}", effective.toml);
+    }
+    if check && !effective.errors.is_empty() {
+        return Err(CliError::InvalidConfig);
+    }
+    Ok(())
+}
 
-    let Err(e) = wayland::run().await;
-    log::error!("A fatal error occurred: {}", Report::new(e));
-    std::process::exit(1);
+async fn connect() -> Result<ConnectionHolder, CliError> {
+    sni::connect().await.map_err(CliError::ConnectDbus)
 }
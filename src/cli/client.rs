@@ -0,0 +1,54 @@
+use {
+    crate::{
+        cli::{connect, CliError},
+        wayland::{
+            ItemInfo, ACTIVATE_ITEM, CONTROL_INTERFACE, CONTROL_NAME, CONTROL_PATH, LIST_ITEMS,
+            OPEN_MENU, RELOAD_CONFIG,
+        },
+    },
+    std::io::{stdout, Write},
+    zbus::names::MemberName,
+};
+
+pub async fn list() -> Result<(), CliError> {
+    let items: Vec<ItemInfo> = call(LIST_ITEMS, &()).await?;
+    let mut out = stdout().lock();
+    for (id, title, status, bus_name, category) in items {
+        writeln!(out, "{id}").map_err(CliError::Stdout)?;
+        for (name, value) in [
+            ("title", title),
+            ("status", status),
+            ("bus-name", bus_name),
+            ("category", category),
+        ] {
+            writeln!(out, "  {name}: {value}").map_err(CliError::Stdout)?;
+        }
+    }
+    Ok(())
+}
+
+pub async fn activate(id: &str) -> Result<(), CliError> {
+    call(ACTIVATE_ITEM, &id).await
+}
+
+pub async fn open_menu(id: &str) -> Result<(), CliError> {
+    call(OPEN_MENU, &id).await
+}
+
+pub async fn reload() -> Result<(), CliError> {
+    call(RELOAD_CONFIG, &()).await
+}
+
+async fn call<R>(
+    member: MemberName<'static>,
+    body: &(impl serde::Serialize + zbus::zvariant::DynamicType),
+) -> Result<R, CliError>
+where
+    R: for<'b> zbus::zvariant::DynamicDeserialize<'b> + Send + 'static,
+{
+    let dbus = connect().await?;
+    dbus.connection
+        .call(CONTROL_NAME, &CONTROL_INTERFACE, CONTROL_PATH, member, body)
+        .await
+        .map_err(CliError::Control)
+}
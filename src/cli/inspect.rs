@@ -0,0 +1,176 @@
+use {
+    crate::{
+        cli::{connect, CliError},
+        sni::{MenuLayoutValue, DBUS_MENU, GET_LAYOUT, ITEM_FDO, ITEM_KDE},
+    },
+    serde_json::{json, Map, Value as Json},
+    std::{
+        collections::{BTreeMap, HashMap},
+        io::{stdout, Write},
+    },
+    zbus::{
+        names::{BusName, InterfaceName, MemberName},
+        zvariant::{ObjectPath, OwnedValue, Signature, Value},
+    },
+};
+
+const DBUS_PROPS_NAME: InterfaceName<'static> =
+    InterfaceName::from_static_str_unchecked("org.freedesktop.DBus.Properties");
+const GET_ALL: MemberName<'static> = MemberName::from_static_str_unchecked("GetAll");
+
+/// Prints the properties and the menu of the item `id`.
+///
+/// `id` has the same format as the ids used in `RegisterStatusNotifierItem`.
+pub async fn run(id: &str, json: bool) -> Result<(), CliError> {
+    let (destination, path) = match id.find("/") {
+        None => (id, "/StatusNotifierItem"),
+        Some(p) => (&id[..p], &id[p..]),
+    };
+    let destination = BusName::try_from(destination.to_string())
+        .map_err(|_| CliError::InvalidBusName(destination.to_string()))?;
+    let path = ObjectPath::try_from(path.to_string())
+        .map_err(|_| CliError::InvalidPath(path.to_string()))?;
+    let dbus = connect().await?;
+    let dbus = &dbus.connection;
+    let get_all = |interface: &InterfaceName<'static>| {
+        dbus.call::<HashMap<String, OwnedValue>>(
+            &destination,
+            DBUS_PROPS_NAME,
+            &path,
+            GET_ALL,
+            &(interface,),
+        )
+    };
+    let mut interface = &ITEM_KDE;
+    let mut props = get_all(interface).await;
+    if props.as_ref().is_ok_and(|p| p.is_empty()) || props.is_err() {
+        if let Ok(p) = get_all(&ITEM_FDO).await {
+            if !p.is_empty() {
+                interface = &ITEM_FDO;
+                props = Ok(p);
+            }
+        }
+    }
+    let props: BTreeMap<_, _> = props
+        .map_err(CliError::GetProperties)?
+        .into_iter()
+        .map(|(k, v)| (k, to_json(&v)))
+        .collect();
+    let menu_path = props.get("Menu").and_then(|p| p.as_str());
+    let menu = match menu_path.and_then(|p| ObjectPath::try_from(p).ok()) {
+        Some(menu_path) if menu_path.as_str() != "/" => {
+            let (_, layout) = dbus
+                .call::<(u32, MenuLayoutValue)>(
+                    &destination,
+                    DBUS_MENU,
+                    &menu_path,
+                    GET_LAYOUT,
+                    &(0i32, -1i32, Vec::<String>::new()),
+                )
+                .await
+                .map_err(CliError::GetLayout)?;
+            Some(layout_to_json(layout))
+        }
+        _ => None,
+    };
+    let mut out = stdout().lock();
+    let res = if json {
+        let res = json!({
+            "bus-name": destination.as_str(),
+            "path": path.as_str(),
+            "interface": interface.as_str(),
+            "properties": props,
+            "menu": menu,
+        });
+        writeln!(out, "{:#}", res)
+    } else {
+        (|| {
+            writeln!(out, "{destination}{path} ({interface})")?;
+            writeln!(out, "properties:")?;
+            for (name, value) in &props {
+                writeln!(out, "  {name}: {value}")?;
+            }
+            if let Some(menu) = &menu {
+                writeln!(out, "menu:")?;
+                write_menu(&mut out, menu, 1)?;
+            }
+            Ok(())
+        })()
+    };
+    res.map_err(CliError::Stdout)
+}
+
+fn write_menu(out: &mut impl Write, menu: &Json, depth: usize) -> std::io::Result<()> {
+    write!(out, "{:1$}{2}", "", depth * 2, menu["id"])?;
+    if let Some(props) = menu["properties"].as_object() {
+        for (name, value) in props {
+            write!(out, " {name}={value}")?;
+        }
+    }
+    writeln!(out)?;
+    if let Some(children) = menu["children"].as_array() {
+        for child in children {
+            write_menu(out, child, depth + 1)?;
+        }
+    }
+    Ok(())
+}
+
+fn layout_to_json(layout: MenuLayoutValue) -> Json {
+    let properties: BTreeMap<_, _> = layout
+        .properties
+        .iter()
+        .map(|(k, v)| (k.clone(), to_json(v)))
+        .collect();
+    let children: Vec<_> = layout
+        .children
+        .into_iter()
+        .filter_map(|c| MenuLayoutValue::try_from(c).ok())
+        .map(layout_to_json)
+        .collect();
+    json!({
+        "id": layout.menu_id,
+        "properties": properties,
+        "children": children,
+    })
+}
+
+/// Converts a dbus value to JSON.
+///
+/// Byte arrays, such as icon data, are replaced by their length.
+fn to_json(value: &Value<'_>) -> Json {
+    match value {
+        Value::U8(v) => json!(v),
+        Value::Bool(v) => json!(v),
+        Value::I16(v) => json!(v),
+        Value::U16(v) => json!(v),
+        Value::I32(v) => json!(v),
+        Value::U32(v) => json!(v),
+        Value::I64(v) => json!(v),
+        Value::U64(v) => json!(v),
+        Value::F64(v) => json!(v),
+        Value::Str(v) => json!(v.as_str()),
+        Value::Signature(v) => json!(v.to_string()),
+        Value::ObjectPath(v) => json!(v.as_str()),
+        Value::Value(v) => to_json(v),
+        Value::Array(v) => match v.element_signature() == &Signature::U8 {
+            true => json!(format!("<{} bytes>", v.len())),
+            false => v.inner().iter().map(to_json).collect(),
+        },
+        Value::Dict(v) => {
+            let map: Map<_, _> = v
+                .iter()
+                .map(|(k, v)| {
+                    let k = match to_json(k) {
+                        Json::String(s) => s,
+                        k => k.to_string(),
+                    };
+                    (k, to_json(v))
+                })
+                .collect();
+            Json::Object(map)
+        }
+        Value::Structure(v) => v.fields().iter().map(to_json).collect(),
+        Value::Fd(v) => json!(format!("<fd {v:?}>")),
+    }
+}
@@ -1,18 +1,45 @@
 use {
+    crate::{
+        settings::{
+            field::{Field, FieldError, FieldExt},
+            item::TomlItemRule,
+        },
+        sni::SniItemProperties,
+    },
+    arc_swap::ArcSwapOption,
     error_reporter::Report,
     pangocairo::{cairo, pango::FontDescription},
-    serde::{de::Error, Deserialize, Deserializer},
-    std::{env::var, fs::File, io::Write, sync::OnceLock},
+    serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer},
+    std::{
+        env::var,
+        fs::File,
+        io::{self, Write},
+        mem,
+        sync::{Arc, OnceLock},
+    },
+};
+pub use {
+    field::ConfigError,
+    item::{ClickAction, ItemOverrides},
+    watch::watch,
 };
 
+mod field;
+mod item;
+mod watch;
+
 #[derive(Clone, Debug)]
 pub struct Settings {
     pub icon: IconSettings,
     pub scale: f64,
     pub menu: MenuSettings,
     pub tooltip: TooltipSettings,
+    pub label: LabelSettings,
+    pub panel: PanelSettings,
     pub theme: String,
     pub keep_open: bool,
+    pub update_interval: f64,
+    pub items: Vec<item::ItemRule>,
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Default)]
@@ -26,6 +53,7 @@ pub struct ThemeColor {
 #[derive(Clone, Debug)]
 pub struct IconSettings {
     pub color: ThemeColor,
+    pub attention_blink_interval: f64,
 }
 
 #[derive(Clone, Debug)]
@@ -33,6 +61,9 @@ pub struct MenuSettings {
     pub font: FontDescription,
     pub color: ThemeColor,
     pub disabled_color: ThemeColor,
+    pub informative_color: ThemeColor,
+    pub warning_color: ThemeColor,
+    pub alert_color: ThemeColor,
     pub background_color: ThemeColor,
     pub hover_color: ThemeColor,
     pub hover_background_color: ThemeColor,
@@ -40,6 +71,7 @@ pub struct MenuSettings {
     pub border_width: f64,
     pub padding: f64,
     pub rtl: bool,
+    pub scrollable: bool,
 }
 
 #[derive(Clone, Debug)]
@@ -50,6 +82,47 @@ pub struct TooltipSettings {
     pub border_color: ThemeColor,
     pub border_width: f64,
     pub padding: f64,
+    pub max_width: f64,
+    pub wrap: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LabelSettings {
+    pub mode: LabelMode,
+    pub font: FontDescription,
+    pub color: ThemeColor,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LabelMode {
+    Beside,
+    Instead,
+    None,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PanelSettings {
+    pub edge: PanelEdge,
+    pub size: f64,
+    pub spacing: f64,
+    pub background_color: ThemeColor,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PanelEdge {
+    Top,
+    Bottom,
+    Left,
+    Right,
+}
+
+impl Settings {
+    /// Returns the overrides of the `[[item]]` rules that match the item.
+    pub fn item_overrides(&self, props: &SniItemProperties, bus_name: &str) -> ItemOverrides {
+        item::overrides(&self.items, props, bus_name)
+    }
 }
 
 impl ThemeColor {
@@ -69,64 +142,125 @@ impl From<TomlColor> for ThemeColor {
     }
 }
 
-static SETTINGS: OnceLock<Settings> = OnceLock::new();
+static SETTINGS: ArcSwapOption<Settings> = ArcSwapOption::const_empty();
+static PATH: OnceLock<Option<String>> = OnceLock::new();
 
-pub fn get() -> &'static Settings {
-    match SETTINGS.get() {
+pub fn get() -> Arc<Settings> {
+    match SETTINGS.load_full() {
         None => panic!("settings have not been initialized"),
         Some(s) => s,
     }
 }
 
+/// Returns the path of the config file or `None` if the default config is used.
+pub fn path() -> Option<&'static str> {
+    PATH.get()?.as_deref()
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum LoadError {
+    #[error("No config file is in use")]
+    NoConfigFile,
+    #[error("Could not read {0}")]
+    Read(String, #[source] io::Error),
+    #[error("Could not deserialize {0}")]
+    Deserialize(String, #[source] ConfigError),
+}
+
+/// The config as it would be used by the bridge.
+pub struct EffectiveConfig {
+    pub path: String,
+    /// The merged config in TOML format.
+    pub toml: String,
+    /// The invalid values that have been replaced by their defaults.
+    pub errors: Vec<ConfigError>,
+}
+
 pub fn init(config: Option<&str>) {
-    SETTINGS.get_or_init(|| {
-        let path_str;
-        let path = if let Some(config) = config {
-            config
-        } else {
-            let config_home = match var("XDG_CONFIG_HOME") {
-                Ok(h) => h,
-                Err(_) => match var("HOME") {
-                    Ok(v) => format!("{v}/.config"),
-                    Err(_) => {
-                        log::error!("Neither $XDG_CONFIG_HOME nor $HOME are defined");
-                        log::warn!("Using default config");
-                        return Settings::default();
-                    }
-                },
-            };
-            let path = format!("{config_home}/wl-tray-bridge");
-            if let Err(e) = std::fs::create_dir_all(&path) {
-                log::error!("Could not create {path}: {}", Report::new(e));
-                log::warn!("Using default config");
-                return Settings::default();
-            }
-            path_str = format!("{path}/config.toml");
-            if let Ok(mut file) = File::options().create_new(true).write(true).open(&path) {
-                if let Err(e) = file.write_all(DEFAULT_TOML.as_bytes()) {
-                    log::error!(
-                        "Could not write default config to {path}: {}",
-                        Report::new(e)
-                    );
-                }
-            }
-            &path_str
-        };
-        let c = match std::fs::read_to_string(path) {
-            Ok(c) => c,
+    let path = PATH.get_or_init(|| find_path(config));
+    let settings = match path {
+        None => {
+            log::warn!("Using default config");
+            Settings::default()
+        }
+        Some(path) => match std::fs::read_to_string(path) {
+            Ok(c) => deserialize(path, &c),
             Err(e) => {
                 log::error!("Could not read {path}: {}", Report::new(e));
                 log::warn!("Using default config");
-                return Settings::default();
+                Settings::default()
             }
-        };
-        deserialize(&c)
-    });
+        },
+    };
+    SETTINGS.store(Some(Arc::new(settings)));
+}
+
+/// Reads the config file again.
+///
+/// If the file cannot be read or is invalid, the current settings are retained.
+/// Returns the previous settings.
+pub fn reload() -> Result<Arc<Settings>, LoadError> {
+    let Some(path) = path() else {
+        return Err(LoadError::NoConfigFile);
+    };
+    let c = std::fs::read_to_string(path).map_err(|e| LoadError::Read(path.to_string(), e))?;
+    let (toml, errors) = parse(&c).map_err(|e| LoadError::Deserialize(path.to_string(), e))?;
+    log_errors(path, &errors);
+    let settings = build(toml);
+    match SETTINGS.swap(Some(Arc::new(settings))) {
+        None => panic!("settings have not been initialized"),
+        Some(old) => Ok(old),
+    }
+}
+
+/// Reads the config file without applying it.
+pub fn load_effective(config: Option<&str>) -> Result<EffectiveConfig, LoadError> {
+    let Some(path) = find_path(config) else {
+        return Err(LoadError::NoConfigFile);
+    };
+    let c = std::fs::read_to_string(&path).map_err(|e| LoadError::Read(path.clone(), e))?;
+    let (toml, errors) = parse(&c).map_err(|e| LoadError::Deserialize(path.clone(), e))?;
+    let toml = match toml::to_string(&toml) {
+        Ok(t) => t,
+        Err(e) => panic!("could not serialize the config: {}", Report::new(e)),
+    };
+    Ok(EffectiveConfig { path, toml, errors })
+}
+
+fn find_path(config: Option<&str>) -> Option<String> {
+    if let Some(config) = config {
+        return Some(config.to_string());
+    }
+    let config_home = match var("XDG_CONFIG_HOME") {
+        Ok(h) => h,
+        Err(_) => match var("HOME") {
+            Ok(v) => format!("{v}/.config"),
+            Err(_) => {
+                log::error!("Neither $XDG_CONFIG_HOME nor $HOME are defined");
+                return None;
+            }
+        },
+    };
+    let path = format!("{config_home}/wl-tray-bridge");
+    if let Err(e) = std::fs::create_dir_all(&path) {
+        log::error!("Could not create {path}: {}", Report::new(e));
+        return None;
+    }
+    let path = format!("{path}/config.toml");
+    if let Ok(mut file) = File::options().create_new(true).write(true).open(&path) {
+        if let Err(e) = file.write_all(DEFAULT_TOML.as_bytes()) {
+            log::error!(
+                "Could not write default config to {path}: {}",
+                Report::new(e)
+            );
+        }
+    }
+    Some(path)
 }
 
 impl Default for Settings {
     fn default() -> Self {
-        deserialize("")
+        build(parse("").unwrap().0)
     }
 }
 
@@ -193,9 +327,58 @@ impl<'de> Deserialize<'de> for TomlColor {
     }
 }
 
-fn merge(target: &mut TomlSettings, mut source: TomlSettings) {
+impl Serialize for TomlColor {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let [r, g, b, a] = [self.r, self.g, self.b, self.a].map(|v| (v * 255.0).round() as u8);
+        serializer.serialize_str(&format!("#{r:02x}{g:02x}{b:02x}{a:02x}"))
+    }
+}
+
+/// A non-negative number of seconds.
+#[derive(Serialize)]
+pub struct TomlInterval(f64);
+
+impl TomlInterval {
+    /// The largest accepted interval.
+    const MAX: f64 = 3600.0;
+}
+
+impl<'de> Deserialize<'de> for TomlInterval {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let v = f64::deserialize(deserializer)?;
+        if !(0.0..=Self::MAX).contains(&v) {
+            return Err(Error::custom(format!(
+                "Interval must be between 0 and {} seconds",
+                Self::MAX
+            )));
+        }
+        Ok(Self(v))
+    }
+}
+
+/// Merges the defaults into the user config.
+///
+/// Invalid values in the user config are replaced by their defaults.
+fn merge(
+    target: &mut TomlSettings,
+    mut source: TomlSettings,
+    errors: &mut Vec<(String, FieldError)>,
+) {
+    for (idx, rule) in mem::take(&mut target.item).into_iter().enumerate() {
+        match rule {
+            Field::Valid(_) => target.item.push(rule),
+            Field::Invalid(e) => errors.push((format!("item[{idx}]"), e)),
+        }
+    }
     macro_rules! opt {
         ($($ident:ident).+) => {
+            Field::take_error(&mut target.$($ident).+, stringify!($($ident).+), errors);
             let v = source.$($ident).+.take();
             if target.$($ident).+.is_none() {
                 target.$($ident).+ = v;
@@ -204,113 +387,253 @@ fn merge(target: &mut TomlSettings, mut source: TomlSettings) {
     }
     opt!(scale);
     opt!(keep_open);
+    opt!(update_interval);
     opt!(theme);
     opt!(icon.color);
+    opt!(icon.attention_blink_interval);
     opt!(menu.font);
     opt!(menu.color);
     opt!(menu.background_color);
     opt!(menu.hover_color);
     opt!(menu.hover_background_color);
     opt!(menu.disabled_color);
+    opt!(menu.informative_color);
+    opt!(menu.warning_color);
+    opt!(menu.alert_color);
     opt!(menu.border_color);
     opt!(menu.border_width);
     opt!(menu.padding);
     opt!(menu.right_to_left);
+    opt!(menu.scrollable);
     opt!(tooltip.font);
     opt!(tooltip.color);
     opt!(tooltip.background_color);
     opt!(tooltip.border_color);
     opt!(tooltip.border_width);
     opt!(tooltip.padding);
+    opt!(tooltip.max_width);
+    opt!(tooltip.wrap);
+    opt!(label.mode);
+    opt!(label.font);
+    opt!(label.color);
+    opt!(panel.edge);
+    opt!(panel.size);
+    opt!(panel.spacing);
+    opt!(panel.background_color);
 }
 
 const DEFAULT_TOML: &str = include_str!("default.toml");
 
 #[test]
 fn empty_deserializes() {
-    deserialize("");
+    let (_, errors) = parse("").unwrap();
+    assert!(errors.is_empty());
+}
+
+#[test]
+fn invalid_values_are_replaced() {
+    let (toml, errors) = parse("theme = \"Adwaita\"\n[menu]\ncolor = \"red\"\n").unwrap();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].path, "menu.color");
+    assert_eq!(errors[0].position, Some((3, 9)));
+    let settings = build(toml);
+    assert_eq!(settings.theme, "Adwaita");
+    assert_eq!(settings.menu.color, Settings::default().menu.color);
+}
+
+#[test]
+fn unknown_item_keys_are_reported() {
+    let (toml, errors) = parse("[[item]]\nttile = \"Foo\"\nhide = true\n").unwrap();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].path, "item[0]");
+    assert_eq!(errors[0].position, Some((1, 1)));
+    assert!(build(toml).items.is_empty());
+}
+
+#[test]
+fn intervals_are_bounded() {
+    for v in ["-1.0", "inf", "nan", "1e20"] {
+        let (_, errors) = parse(&format!("[icon]\nattention-blink-interval = {v}\n")).unwrap();
+        assert_eq!(errors.len(), 1, "{v}");
+        assert_eq!(errors[0].path, "icon.attention-blink-interval");
+        let (_, errors) = parse(&format!("update-interval = {v}\n")).unwrap();
+        assert_eq!(errors.len(), 1, "{v}");
+        assert_eq!(errors[0].path, "update-interval");
+    }
 }
 
-fn deserialize(s: &str) -> Settings {
+fn deserialize(path: &str, s: &str) -> Settings {
+    match parse(s) {
+        Ok((toml, errors)) => {
+            log_errors(path, &errors);
+            build(toml)
+        }
+        Err(e) => {
+            log::error!("Could not deserialize {path}: {}", Report::new(e));
+            log::warn!("Falling back to default settings");
+            Settings::default()
+        }
+    }
+}
+
+fn log_errors(path: &str, errors: &[ConfigError]) {
+    for e in errors {
+        log::error!("Invalid value in {path}: {e}");
+    }
+    if !errors.is_empty() {
+        log::warn!("Using the default values instead");
+    }
+}
+
+/// Parses the config and merges it with the defaults.
+///
+/// Only errors that affect the whole file cause this function to fail. Otherwise
+/// invalid values are returned as errors and replaced by their defaults.
+fn parse(s: &str) -> Result<(TomlSettings, Vec<ConfigError>), ConfigError> {
     let default = toml::from_str::<TomlSettings>(DEFAULT_TOML).unwrap();
-    let mut desired = toml::from_str::<TomlSettings>(s).unwrap_or_else(|e| {
-        log::error!("Could not deserialize settings: {}", Report::new(e));
-        log::warn!("Falling back to default settings");
-        TomlSettings::default()
-    });
-    merge(&mut desired, default);
+    let mut desired =
+        toml::from_str::<TomlSettings>(s).map_err(|e| ConfigError::from_toml(s, e))?;
+    let mut errors = vec![];
+    merge(&mut desired, default, &mut errors);
+    let mut errors: Vec<_> = errors
+        .into_iter()
+        .map(|(path, e)| ConfigError::new(s, path, e))
+        .collect();
+    errors.sort_by_key(|e| e.position);
+    Ok((desired, errors))
+}
+
+fn build(desired: TomlSettings) -> Settings {
     Settings {
-        theme: desired.theme.unwrap(),
-        keep_open: desired.keep_open.unwrap(),
+        theme: desired.theme.value(),
+        keep_open: desired.keep_open.value(),
+        update_interval: desired.update_interval.value().0,
+        items: desired
+            .item
+            .into_iter()
+            .filter_map(|r| match r {
+                Field::Valid(r) => Some(r.into()),
+                Field::Invalid(_) => None,
+            })
+            .collect(),
         icon: IconSettings {
-            color: desired.icon.color.unwrap().into(),
+            color: desired.icon.color.value().into(),
+            attention_blink_interval: desired.icon.attention_blink_interval.value().0,
         },
-        scale: desired.scale.unwrap(),
+        scale: desired.scale.value(),
         menu: MenuSettings {
-            font: FontDescription::from_string(&desired.menu.font.unwrap()),
-            color: desired.menu.color.unwrap().into(),
-            disabled_color: desired.menu.disabled_color.unwrap().into(),
-            background_color: desired.menu.background_color.unwrap().into(),
-            hover_color: desired.menu.hover_color.unwrap().into(),
-            hover_background_color: desired.menu.hover_background_color.unwrap().into(),
-            border_color: desired.menu.border_color.unwrap().into(),
-            border_width: desired.menu.border_width.unwrap(),
-            padding: desired.menu.padding.unwrap(),
-            rtl: desired.menu.right_to_left.unwrap(),
+            font: FontDescription::from_string(&desired.menu.font.value()),
+            color: desired.menu.color.value().into(),
+            disabled_color: desired.menu.disabled_color.value().into(),
+            informative_color: desired.menu.informative_color.value().into(),
+            warning_color: desired.menu.warning_color.value().into(),
+            alert_color: desired.menu.alert_color.value().into(),
+            background_color: desired.menu.background_color.value().into(),
+            hover_color: desired.menu.hover_color.value().into(),
+            hover_background_color: desired.menu.hover_background_color.value().into(),
+            border_color: desired.menu.border_color.value().into(),
+            border_width: desired.menu.border_width.value(),
+            padding: desired.menu.padding.value(),
+            rtl: desired.menu.right_to_left.value(),
+            scrollable: desired.menu.scrollable.value(),
         },
         tooltip: TooltipSettings {
-            font: FontDescription::from_string(&desired.tooltip.font.unwrap()),
-            color: desired.tooltip.color.unwrap().into(),
-            background_color: desired.tooltip.background_color.unwrap().into(),
-            border_color: desired.tooltip.border_color.unwrap().into(),
-            border_width: desired.tooltip.border_width.unwrap(),
-            padding: desired.tooltip.padding.unwrap(),
+            font: FontDescription::from_string(&desired.tooltip.font.value()),
+            color: desired.tooltip.color.value().into(),
+            background_color: desired.tooltip.background_color.value().into(),
+            border_color: desired.tooltip.border_color.value().into(),
+            border_width: desired.tooltip.border_width.value(),
+            padding: desired.tooltip.padding.value(),
+            max_width: desired.tooltip.max_width.value(),
+            wrap: desired.tooltip.wrap.value(),
+        },
+        label: LabelSettings {
+            mode: desired.label.mode.value(),
+            font: FontDescription::from_string(&desired.label.font.value()),
+            color: desired.label.color.value().into(),
+        },
+        panel: PanelSettings {
+            edge: desired.panel.edge.value(),
+            size: desired.panel.size.value(),
+            spacing: desired.panel.spacing.value(),
+            background_color: desired.panel.background_color.value().into(),
         },
     }
 }
 
-#[derive(Deserialize, Default)]
+#[derive(Deserialize, Serialize, Default)]
 #[serde(rename_all = "kebab-case")]
 struct TomlSettings {
-    scale: Option<f64>,
-    keep_open: Option<bool>,
-    theme: Option<String>,
+    scale: Option<Field<f64>>,
+    keep_open: Option<Field<bool>>,
+    update_interval: Option<Field<TomlInterval>>,
+    theme: Option<Field<String>>,
     #[serde(default)]
     icon: TomlIconSettings,
     #[serde(default)]
     menu: TomlMenuSettings,
     #[serde(default)]
     tooltip: TomlTooltipSettings,
+    #[serde(default)]
+    label: TomlLabelSettings,
+    #[serde(default)]
+    panel: TomlPanelSettings,
+    #[serde(default)]
+    item: Vec<Field<TomlItemRule>>,
 }
 
-#[derive(Deserialize, Default)]
+#[derive(Deserialize, Serialize, Default)]
 #[serde(rename_all = "kebab-case")]
 struct TomlIconSettings {
-    color: Option<TomlColor>,
+    color: Option<Field<TomlColor>>,
+    attention_blink_interval: Option<Field<TomlInterval>>,
 }
 
-#[derive(Deserialize, Default)]
+#[derive(Deserialize, Serialize, Default)]
 #[serde(rename_all = "kebab-case")]
 struct TomlMenuSettings {
-    font: Option<String>,
-    color: Option<TomlColor>,
-    background_color: Option<TomlColor>,
-    hover_color: Option<TomlColor>,
-    hover_background_color: Option<TomlColor>,
-    disabled_color: Option<TomlColor>,
-    border_color: Option<TomlColor>,
-    border_width: Option<f64>,
-    padding: Option<f64>,
-    right_to_left: Option<bool>,
-}
-
-#[derive(Deserialize, Default)]
+    font: Option<Field<String>>,
+    color: Option<Field<TomlColor>>,
+    background_color: Option<Field<TomlColor>>,
+    hover_color: Option<Field<TomlColor>>,
+    hover_background_color: Option<Field<TomlColor>>,
+    disabled_color: Option<Field<TomlColor>>,
+    informative_color: Option<Field<TomlColor>>,
+    warning_color: Option<Field<TomlColor>>,
+    alert_color: Option<Field<TomlColor>>,
+    border_color: Option<Field<TomlColor>>,
+    border_width: Option<Field<f64>>,
+    padding: Option<Field<f64>>,
+    right_to_left: Option<Field<bool>>,
+    scrollable: Option<Field<bool>>,
+}
+
+#[derive(Deserialize, Serialize, Default)]
 #[serde(rename_all = "kebab-case")]
 struct TomlTooltipSettings {
-    font: Option<String>,
-    color: Option<TomlColor>,
-    background_color: Option<TomlColor>,
-    border_color: Option<TomlColor>,
-    border_width: Option<f64>,
-    padding: Option<f64>,
+    font: Option<Field<String>>,
+    color: Option<Field<TomlColor>>,
+    background_color: Option<Field<TomlColor>>,
+    border_color: Option<Field<TomlColor>>,
+    border_width: Option<Field<f64>>,
+    padding: Option<Field<f64>>,
+    max_width: Option<Field<f64>>,
+    wrap: Option<Field<bool>>,
+}
+
+#[derive(Deserialize, Serialize, Default)]
+#[serde(rename_all = "kebab-case")]
+struct TomlLabelSettings {
+    mode: Option<Field<LabelMode>>,
+    font: Option<Field<String>>,
+    color: Option<Field<TomlColor>>,
+}
+
+#[derive(Deserialize, Serialize, Default)]
+#[serde(rename_all = "kebab-case")]
+struct TomlPanelSettings {
+    edge: Option<Field<PanelEdge>>,
+    size: Option<Field<f64>>,
+    spacing: Option<Field<f64>>,
+    background_color: Option<Field<TomlColor>>,
 }
@@ -0,0 +1,134 @@
+use {
+    serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize, Serializer},
+    std::{
+        fmt::{Display, Formatter},
+        ops::Range,
+    },
+    toml::{Spanned, Value},
+};
+
+/// A config value that is kept even if it is invalid so that the rest of the config
+/// can still be used.
+#[derive(Debug)]
+pub enum Field<T> {
+    Valid(T),
+    Invalid(FieldError),
+}
+
+#[derive(Debug)]
+pub struct FieldError {
+    span: Range<usize>,
+    message: String,
+}
+
+/// A problem in the config file.
+#[derive(Debug)]
+pub struct ConfigError {
+    /// The TOML path of the value or an empty string if the whole file is invalid.
+    pub path: String,
+    /// The 1-based line and column.
+    pub position: Option<(usize, usize)>,
+    pub message: String,
+}
+
+impl<T> Field<T> {
+    /// Replaces the field by `None` if it is invalid and records the error.
+    pub fn take_error(
+        field: &mut Option<Self>,
+        path: &str,
+        errors: &mut Vec<(String, FieldError)>,
+    ) {
+        if let Some(Field::Invalid(e)) = field.take_if(|f| matches!(f, Field::Invalid(_))) {
+            errors.push((path.replace('_', "-"), e));
+        }
+    }
+}
+
+pub trait FieldExt<T> {
+    /// Returns the value of a field that has been validated.
+    fn value(self) -> T;
+}
+
+impl<T> FieldExt<T> for Option<Field<T>> {
+    fn value(self) -> T {
+        match self {
+            Some(Field::Valid(v)) => v,
+            _ => panic!("field has not been validated"),
+        }
+    }
+}
+
+impl<'de, T> Deserialize<'de> for Field<T>
+where
+    T: DeserializeOwned,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = Spanned::<Value>::deserialize(deserializer)?;
+        let span = value.span();
+        match T::deserialize(value.into_inner()) {
+            Ok(v) => Ok(Field::Valid(v)),
+            Err(e) => Ok(Field::Invalid(FieldError {
+                span,
+                message: e.message().trim().to_string(),
+            })),
+        }
+    }
+}
+
+impl<T> Serialize for Field<T>
+where
+    T: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            Field::Valid(v) => v.serialize(serializer),
+            Field::Invalid(_) => serializer.serialize_none(),
+        }
+    }
+}
+
+impl ConfigError {
+    pub fn new(src: &str, path: String, error: FieldError) -> Self {
+        Self {
+            path,
+            position: Some(position(src, error.span.start)),
+            message: error.message,
+        }
+    }
+
+    pub fn from_toml(src: &str, error: toml::de::Error) -> Self {
+        Self {
+            path: String::new(),
+            position: error.span().map(|s| position(src, s.start)),
+            message: error.message().trim().to_string(),
+        }
+    }
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if !self.path.is_empty() {
+            write!(f, "{}: ", self.path)?;
+        }
+        if let Some((line, column)) = self.position {
+            write!(f, "line {line}, column {column}: ")?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+fn position(src: &str, offset: usize) -> (usize, usize) {
+    let before = &src[..offset.min(src.len())];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map(|p| p + 1).unwrap_or(0);
+    let column = before[line_start..].chars().count() + 1;
+    (line, column)
+}
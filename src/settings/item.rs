@@ -0,0 +1,216 @@
+use {
+    crate::{
+        settings::{ThemeColor, TomlColor},
+        sni::SniItemProperties,
+    },
+    regex::Regex,
+    serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer},
+    std::sync::Arc,
+};
+
+/// A `[[item]]` rule from the config file.
+#[derive(Clone, Debug)]
+pub struct ItemRule {
+    id: Option<Pattern>,
+    category: Option<Pattern>,
+    title: Option<Pattern>,
+    bus_name: Option<Pattern>,
+    hide: Option<bool>,
+    icon: Option<Arc<String>>,
+    color: Option<ThemeColor>,
+    left_click: Option<ClickAction>,
+    middle_click: Option<ClickAction>,
+    right_click: Option<ClickAction>,
+    priority: Option<i32>,
+}
+
+/// The action performed when a tray icon is clicked.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ClickAction {
+    Activate,
+    SecondaryActivate,
+    Menu,
+    ContextMenu,
+    None,
+}
+
+/// The combined effect of all rules that match an item.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ItemOverrides {
+    pub hide: bool,
+    pub icon: Option<Arc<String>>,
+    pub color: Option<ThemeColor>,
+    pub left_click: Option<ClickAction>,
+    pub middle_click: Option<ClickAction>,
+    pub right_click: Option<ClickAction>,
+    pub priority: i32,
+}
+
+#[derive(Clone, Debug)]
+struct Pattern {
+    regex: Regex,
+    source: TomlPattern,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+enum TomlPattern {
+    Glob(String),
+    Regex { regex: String },
+}
+
+impl Pattern {
+    fn matches(&self, s: Option<&str>) -> bool {
+        self.regex.is_match(s.unwrap_or_default())
+    }
+}
+
+impl<'de> Deserialize<'de> for Pattern {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let source = TomlPattern::deserialize(deserializer)?;
+        let regex = match &source {
+            TomlPattern::Glob(glob) => glob_to_regex(glob),
+            TomlPattern::Regex { regex } => regex.clone(),
+        };
+        let regex = Regex::new(&regex).map_err(Error::custom)?;
+        Ok(Pattern { regex, source })
+    }
+}
+
+impl Serialize for Pattern {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.source.serialize(serializer)
+    }
+}
+
+fn glob_to_regex(glob: &str) -> String {
+    let mut res = String::with_capacity(glob.len() + 2);
+    res.push('^');
+    for c in glob.chars() {
+        match c {
+            '*' => res.push_str(".*"),
+            '?' => res.push('.'),
+            _ => res.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+        }
+    }
+    res.push('$');
+    res
+}
+
+impl ItemRule {
+    fn matches(&self, props: &SniItemProperties, bus_name: &str) -> bool {
+        let matches = |pattern: &Option<Pattern>, s: Option<&str>| {
+            pattern.as_ref().is_none_or(|p| p.matches(s))
+        };
+        fn prop(s: &Option<Arc<String>>) -> Option<&str> {
+            s.as_deref().map(String::as_str)
+        }
+        matches(&self.id, prop(&props.id))
+            && matches(&self.category, prop(&props.category))
+            && matches(&self.title, prop(&props.title))
+            && matches(&self.bus_name, Some(bus_name))
+    }
+}
+
+/// Combines the rules that match the item.
+///
+/// If multiple rules set the same option, the first rule wins.
+pub fn overrides(rules: &[ItemRule], props: &SniItemProperties, bus_name: &str) -> ItemOverrides {
+    let mut hide = None;
+    let mut priority = None;
+    let mut res = ItemOverrides::default();
+    for rule in rules.iter().filter(|r| r.matches(props, bus_name)) {
+        macro_rules! opt {
+            ($target:expr, $field:ident) => {
+                if $target.is_none() {
+                    $target = rule.$field.clone();
+                }
+            };
+        }
+        opt!(hide, hide);
+        opt!(priority, priority);
+        opt!(res.icon, icon);
+        opt!(res.color, color);
+        opt!(res.left_click, left_click);
+        opt!(res.middle_click, middle_click);
+        opt!(res.right_click, right_click);
+    }
+    res.hide = hide.unwrap_or_default();
+    res.priority = priority.unwrap_or_default();
+    res
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub(super) struct TomlItemRule {
+    id: Option<Pattern>,
+    category: Option<Pattern>,
+    title: Option<Pattern>,
+    bus_name: Option<Pattern>,
+    hide: Option<bool>,
+    icon_name: Option<String>,
+    icon_file: Option<String>,
+    color: Option<TomlColor>,
+    left_click: Option<ClickAction>,
+    middle_click: Option<ClickAction>,
+    right_click: Option<ClickAction>,
+    priority: Option<i32>,
+}
+
+impl From<TomlItemRule> for ItemRule {
+    fn from(value: TomlItemRule) -> Self {
+        Self {
+            id: value.id,
+            category: value.category,
+            title: value.title,
+            bus_name: value.bus_name,
+            hide: value.hide,
+            icon: value.icon_file.or(value.icon_name).map(Arc::new),
+            color: value.color.map(Into::into),
+            left_click: value.left_click,
+            middle_click: value.middle_click,
+            right_click: value.right_click,
+            priority: value.priority,
+        }
+    }
+}
+
+#[test]
+fn globs() {
+    let glob = |glob: &str, s: &str| Regex::new(&glob_to_regex(glob)).unwrap().is_match(s);
+    assert!(glob("a.b+(c)", "a.b+(c)"));
+    assert!(!glob("a.b", "axb"));
+    assert!(!glob("a+", "aa"));
+    assert!(glob("nm-*", "nm-applet"));
+    assert!(!glob("nm-*", "x-nm-applet"));
+    assert!(glob("?pp", "app"));
+    assert!(!glob("?pp", "apps"));
+    assert!(!glob("?pp", "pp"));
+}
+
+#[test]
+fn first_rule_wins() {
+    let rule = |s: &str| ItemRule::from(toml::from_str::<TomlItemRule>(s).unwrap());
+    let rules = [
+        rule("id = \"other\"\nhide = true"),
+        rule("id = \"app*\"\npriority = 1"),
+        rule("priority = 2\nleft-click = \"menu\"\nhide = false"),
+        rule("hide = true\nleft-click = \"activate\""),
+    ];
+    let props = SniItemProperties {
+        id: Some(Arc::new("application".to_string())),
+        ..Default::default()
+    };
+    let res = overrides(&rules, &props, ":1.2");
+    assert!(!res.hide);
+    assert_eq!(res.priority, 1);
+    assert_eq!(res.left_click, Some(ClickAction::Menu));
+    assert_eq!(res.right_click, None);
+}
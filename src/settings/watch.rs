@@ -0,0 +1,118 @@
+use {
+    crate::settings,
+    error_reporter::Report,
+    inotify::{Inotify, WatchMask},
+    std::{ffi::OsStr, io, path::Path},
+    thiserror::Error,
+    tokio::{
+        io::unix::AsyncFd,
+        signal::unix::{signal, SignalKind},
+    },
+};
+
+#[derive(Debug, Error)]
+enum WatchError {
+    #[error("Could not create an inotify instance")]
+    Init(#[source] io::Error),
+    #[error("Could not watch {0}")]
+    AddWatch(String, #[source] io::Error),
+    #[error("Could not create a tokio AsyncFd from the inotify fd")]
+    AsyncFd(#[source] io::Error),
+    #[error("Could not read inotify events")]
+    Read(#[source] io::Error),
+}
+
+/// Invokes `cb` whenever the config file changes or the process receives SIGHUP.
+pub fn watch(cb: impl Fn() + Send + 'static) {
+    tokio::spawn(async move {
+        let mut sighup = match signal(SignalKind::hangup()) {
+            Ok(s) => Some(s),
+            Err(e) => {
+                log::error!("Could not install a SIGHUP handler: {}", Report::new(e));
+                None
+            }
+        };
+        let mut inotify = match settings::path().map(watch_file).transpose() {
+            Ok(i) => i,
+            Err(e) => {
+                log::error!("Could not watch the config file: {}", Report::new(e));
+                None
+            }
+        };
+        loop {
+            let changed = async {
+                match &mut inotify {
+                    Some((afd, name)) => wait_for_change(afd, name).await,
+                    None => std::future::pending().await,
+                }
+            };
+            let hangup = async {
+                match &mut sighup {
+                    Some(s) => s.recv().await,
+                    None => std::future::pending().await,
+                }
+            };
+            tokio::select! {
+                res = changed => {
+                    if let Err(e) = res {
+                        log::error!("Could not watch the config file: {}", Report::new(e));
+                        inotify = None;
+                        continue;
+                    }
+                    log::info!("The config file has changed");
+                }
+                _ = hangup => {
+                    log::info!("Received SIGHUP");
+                }
+            }
+            cb();
+        }
+    });
+}
+
+/// Watches the directory containing the file since editors often replace the file.
+fn watch_file(path: &str) -> Result<(AsyncFd<Inotify>, String), WatchError> {
+    let path = Path::new(path);
+    let dir = match path.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p,
+        _ => Path::new("."),
+    };
+    let name = path
+        .file_name()
+        .and_then(OsStr::to_str)
+        .unwrap_or_default()
+        .to_string();
+    let inotify = Inotify::init().map_err(WatchError::Init)?;
+    inotify
+        .watches()
+        .add(dir, WatchMask::CLOSE_WRITE | WatchMask::MOVED_TO)
+        .map_err(|e| WatchError::AddWatch(dir.display().to_string(), e))?;
+    let afd = AsyncFd::new(inotify).map_err(WatchError::AsyncFd)?;
+    Ok((afd, name))
+}
+
+/// Waits until the file `name` has been written or moved into place.
+async fn wait_for_change(afd: &mut AsyncFd<Inotify>, name: &str) -> Result<(), WatchError> {
+    let mut buf = [0; 4096];
+    loop {
+        let mut guard = afd.readable_mut().await.map_err(WatchError::Read)?;
+        let mut changed = false;
+        loop {
+            match guard.get_inner_mut().read_events(&mut buf) {
+                Ok(events) => {
+                    for event in events {
+                        changed |= event.name.and_then(OsStr::to_str) == Some(name);
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    guard.clear_ready();
+                    break;
+                }
+                Err(e) => return Err(WatchError::Read(e)),
+            }
+        }
+        if changed {
+            return Ok(());
+        }
+    }
+}
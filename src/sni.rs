@@ -1,18 +1,57 @@
 pub use host::{
     item::{
-        IconFrame, IconFrames, MutableProperty, SniItem, SniItemId, SniItemOwner, SniItemProperties,
+        IconFrame, IconFrames, MutableProperty, SniItem, SniItemId, SniItemOwner,
+        SniItemProperties, ITEM_FDO, ITEM_KDE,
+    },
+    menu::{
+        MenuLayoutValue, SniMenuAccessKey, SniMenuDelta, SniMenuDisposition, SniMenuToggleState,
+        SniMenuToggleType, DBUS_MENU, GET_LAYOUT,
+    },
+};
+use {
+    bussy::{Connection, ConnectionHolder},
+    std::{convert::Infallible, sync::Arc, time::Duration},
+    zbus::{
+        names::{InterfaceName, MemberName, WellKnownName},
+        zvariant::ObjectPath,
     },
-    menu::{SniMenuDelta, SniMenuToggleType},
 };
-use {bussy::Connection, std::sync::Arc};
 
 mod host;
 mod watcher;
 
-pub fn spawn<CB>(conn: &Arc<Connection>, cb: CB)
+/// The timeout of dbus calls that do not use a timeout of their own.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(25);
+
+const DBUS_NAME: WellKnownName<'static> =
+    WellKnownName::from_static_str_unchecked("org.freedesktop.DBus");
+const DBUS_INTERFACE: InterfaceName<'static> =
+    InterfaceName::from_static_str_unchecked("org.freedesktop.DBus");
+const DBUS_PATH: ObjectPath<'static> =
+    ObjectPath::from_static_str_unchecked("/org/freedesktop/DBus");
+const GET_NAME_OWNER: MemberName<'static> = MemberName::from_static_str_unchecked("GetNameOwner");
+
+pub fn spawn<CB>(conn: &Arc<Connection>, watcher: bool, cb: CB)
 where
     CB: Fn(&Arc<SniItem>) + Send + Sync + 'static,
 {
-    watcher::create_watcher(conn);
+    if watcher {
+        watcher::create_watcher(conn);
+    }
     host::create_hosts(conn, cb);
 }
+
+/// Connects to the session bus.
+pub async fn connect() -> Result<ConnectionHolder, zbus::Error> {
+    let dbus = zbus::Connection::session().await?;
+    let dbus = Connection::wrap(&dbus);
+    dbus.connection.set_default_timeout(Some(DEFAULT_TIMEOUT));
+    Ok(dbus)
+}
+
+/// Acts as a StatusNotifierWatcher without hosting any items.
+pub async fn run_watcher() -> Result<Infallible, zbus::Error> {
+    let dbus = connect().await?;
+    watcher::create_watcher(&dbus.connection);
+    std::future::pending().await
+}
@@ -6,16 +6,21 @@ use {
             REGISTERED_STATUS_NOTIFIER_ITEMS, REGISTER_STATUS_NOTIFIER_HOST,
             STATUS_NOTIFIER_ITEM_REGISTERED, STATUS_NOTIFIER_ITEM_UNREGISTERED, WATCHER_PATH,
         },
+        DBUS_INTERFACE, DBUS_NAME, DBUS_PATH, GET_NAME_OWNER,
     },
     ahash::AHashMap,
-    bussy::Connection,
+    bussy::{BitFlags, Connection},
     isnt::std_1::primitive::IsntStrExt,
     parking_lot::Mutex,
     rand::random,
     std::sync::Arc,
-    zbus::names::WellKnownName,
+    zbus::{
+        names::{BusName, UniqueName, WellKnownName},
+        zvariant::ObjectPath,
+    },
 };
 
+mod coalesce;
 pub mod item;
 pub mod menu;
 
@@ -26,10 +31,123 @@ struct Host {
     fdo_name: WellKnownName<'static>,
     kde_name: WellKnownName<'static>,
     callback: NewItemHandler,
-    items: Mutex<AHashMap<String, Arc<SniItem>>>,
+    data: Mutex<HostMut>,
+}
+
+/// The unique bus name and the object path of an item.
+type ItemKey = (String, String);
+
+#[derive(Default)]
+struct HostMut {
+    items: AHashMap<ItemKey, Arc<SniItem>>,
+    /// The ids registered with the FDO and KDE watchers. The key is `None` while the
+    /// owner of the bus name is being resolved.
+    registrations: AHashMap<(bool, String), Option<ItemKey>>,
 }
 
 impl Host {
+    /// Handles an id registered with a watcher.
+    ///
+    /// Applications might register the same object with both watchers and under
+    /// different bus names. Therefore items are identified by the unique name of the
+    /// owner and the object path, and the first registration that resolves determines
+    /// the interface.
+    fn handle_new_item(self: &Arc<Self>, fdo: bool, id: &str) {
+        let (destination, path) = match id.find("/") {
+            None => (id, "/StatusNotifierItem"),
+            Some(p) => (&id[..p], &id[p..]),
+        };
+        let Ok(destination) = BusName::try_from(destination.to_string()) else {
+            return;
+        };
+        let Ok(path) = ObjectPath::try_from(path.to_string()) else {
+            return;
+        };
+        let registration = (fdo, id.to_string());
+        {
+            let data = &mut *self.data.lock();
+            if data.registrations.contains_key(&registration) {
+                return;
+            }
+            data.registrations.insert(registration.clone(), None);
+        }
+        if let BusName::Unique(owner) = &destination {
+            let key = (owner.to_string(), path.to_string());
+            self.handle_resolved_item(registration, key, destination, path);
+            return;
+        }
+        let h = self.clone();
+        let name = destination.to_string();
+        self.dbus
+            .call_async(
+                DBUS_NAME,
+                DBUS_INTERFACE,
+                DBUS_PATH,
+                GET_NAME_OWNER,
+                &name,
+                move |res: Result<String, _>| match res {
+                    Ok(owner) => {
+                        let key = (owner, path.to_string());
+                        h.handle_resolved_item(registration, key, destination, path);
+                    }
+                    Err(_) => {
+                        h.data.lock().registrations.remove(&registration);
+                    }
+                },
+            )
+            .detach();
+    }
+
+    fn handle_resolved_item(
+        self: &Arc<Self>,
+        registration: (bool, String),
+        key: ItemKey,
+        destination: BusName<'static>,
+        path: ObjectPath<'static>,
+    ) {
+        let Ok(owner) = UniqueName::try_from(key.0.clone()) else {
+            self.data.lock().registrations.remove(&registration);
+            return;
+        };
+        {
+            let data = &mut *self.data.lock();
+            match data.registrations.get_mut(&registration) {
+                Some(r @ None) => *r = Some(key.clone()),
+                _ => return,
+            }
+            if data.items.contains_key(&key) {
+                return;
+            }
+        }
+        // Calls and signals use the unique name since the item outlives the
+        // registration of a well-known name if it has also been registered under
+        // another name.
+        self.create_item(registration.0, key, owner, destination.to_string(), path);
+    }
+
+    /// Handles an id unregistered from a watcher.
+    ///
+    /// The item is only removed once all of its registrations are gone.
+    fn handle_removed_item(&self, fdo: bool, id: &str) {
+        let item = {
+            let data = &mut *self.data.lock();
+            let Some(Some(key)) = data.registrations.remove(&(fdo, id.to_string())) else {
+                return;
+            };
+            if data
+                .registrations
+                .values()
+                .any(|k| k.as_ref() == Some(&key))
+            {
+                return;
+            }
+            data.items.remove(&key)
+        };
+        if let Some(item) = item {
+            item.remove();
+        }
+    }
+
     fn handle_name_owner_changed(self: &Arc<Self>, name: &str) {
         for fdo in [true, false] {
             let (watcher_name, interface, host_name) = match fdo {
@@ -79,7 +197,7 @@ where
         fdo_name: name("freedesktop"),
         kde_name: name("kde"),
         callback: Box::new(cb),
-        items: Default::default(),
+        data: Default::default(),
     });
     for fdo in [true, false] {
         let int = match fdo {
@@ -97,8 +215,8 @@ where
         })
         .detach();
     }
-    dbus.request_name(&host.fdo_name);
-    dbus.request_name(&host.kde_name);
+    dbus.request_name(&host.fdo_name, BitFlags::default());
+    dbus.request_name(&host.kde_name, BitFlags::default());
     let h = host.clone();
     dbus.on_name_owner_changed(move |name, _old, new| {
         if new.is_not_empty() {
@@ -0,0 +1,111 @@
+use {
+    parking_lot::Mutex,
+    std::time::{Duration, Instant},
+};
+
+/// Items that send more signals per second than this are reported.
+const RATE_THRESHOLD: u32 = 20;
+
+/// Coalesces bursts of signals that require the same data to be fetched.
+///
+/// While a fetch is in flight, further signals only mark the data as dirty, and at most
+/// one follow-up fetch runs once the fetch has completed. Consecutive fetches are at
+/// least `update-interval` apart.
+pub struct Coalescer {
+    name: String,
+    state: Mutex<State>,
+}
+
+struct State {
+    in_flight: bool,
+    dirty: bool,
+    last_start: Option<Instant>,
+    window_start: Instant,
+    window_signals: u32,
+    warned: bool,
+}
+
+impl Coalescer {
+    /// Creates a coalescer. `name` identifies the item and the signal in log messages.
+    pub fn new(name: String) -> Self {
+        Self {
+            name,
+            state: Mutex::new(State {
+                in_flight: false,
+                dirty: false,
+                last_start: None,
+                window_start: Instant::now(),
+                window_signals: 0,
+                warned: false,
+            }),
+        }
+    }
+
+    /// Records a signal.
+    ///
+    /// Returns whether the caller must start a fetch. Otherwise a fetch is already in
+    /// flight and the signal is handled by the follow-up fetch.
+    pub fn signal(&self) -> bool {
+        let s = &mut *self.state.lock();
+        let now = Instant::now();
+        if now.duration_since(s.window_start) >= Duration::from_secs(1) {
+            s.window_start = now;
+            s.window_signals = 0;
+        }
+        s.window_signals += 1;
+        if s.window_signals > RATE_THRESHOLD && !s.warned {
+            s.warned = true;
+            log::warn!(
+                "{} is sent more than {RATE_THRESHOLD} times per second",
+                self.name,
+            );
+        }
+        if s.in_flight {
+            s.dirty = true;
+            return false;
+        }
+        s.in_flight = true;
+        true
+    }
+
+    /// Returns how long the caller must wait before starting the fetch so that fetches
+    /// are at least `interval` seconds apart.
+    pub fn delay(&self, interval: f64) -> Duration {
+        let s = &mut *self.state.lock();
+        let interval = Duration::try_from_secs_f64(interval).unwrap_or_default();
+        let now = Instant::now();
+        let start = match s.last_start {
+            Some(last) => (last + interval).max(now),
+            None => now,
+        };
+        s.last_start = Some(start);
+        start - now
+    }
+
+    /// Marks the fetch as completed.
+    ///
+    /// Returns whether the caller must start a follow-up fetch.
+    pub fn complete(&self) -> bool {
+        let s = &mut *self.state.lock();
+        if s.dirty {
+            s.dirty = false;
+            return true;
+        }
+        s.in_flight = false;
+        false
+    }
+}
+
+#[test]
+fn burst_causes_one_follow_up() {
+    let c = Coalescer::new(String::new());
+    assert!(c.signal());
+    assert_eq!(c.delay(0.0), Duration::ZERO);
+    for _ in 0..5 {
+        assert!(!c.signal());
+    }
+    assert!(c.complete());
+    assert!(!c.complete());
+    assert!(!c.state.lock().in_flight);
+    assert!(c.signal());
+}
@@ -1,28 +1,33 @@
 use {
-    crate::sni::{
-        host::{
-            menu::{Menu, DBUS_MENU},
-            Host,
+    crate::{
+        settings,
+        sni::{
+            host::{
+                coalesce::Coalescer,
+                menu::{Menu, DBUS_MENU},
+                Host, ItemKey,
+            },
+            SniMenuDelta, DBUS_INTERFACE, DBUS_NAME, DBUS_PATH,
         },
-        SniMenuDelta,
     },
     arc_swap::ArcSwapOption,
     bussy::{Call, CallFuture, MatchRuleBuilder, SignalHandler},
     parking_lot::Mutex,
     std::{
+        collections::HashMap,
         error::Error,
         fmt::{Debug, Formatter},
         sync::Arc,
-        time::UNIX_EPOCH,
+        time::{Duration, UNIX_EPOCH},
     },
     wayland_client::protocol::wl_pointer::Axis,
     zbus::{
-        names::{BusName, InterfaceName, MemberName},
+        names::{BusName, InterfaceName, MemberName, UniqueName},
         zvariant::{ObjectPath, OwnedObjectPath, OwnedValue, Type, Value},
     },
 };
 
-#[derive(Value, OwnedValue, Type)]
+#[derive(Value, OwnedValue, Type, Clone)]
 pub struct IconPixmap {
     pub width: i32,
     pub height: i32,
@@ -68,9 +73,9 @@ pub struct Tooltip {
     pub text: String,
 }
 
-static ITEM_KDE: InterfaceName<'static> =
+pub static ITEM_KDE: InterfaceName<'static> =
     InterfaceName::from_static_str_unchecked("org.kde.StatusNotifierItem");
-static ITEM_FDO: InterfaceName<'static> =
+pub static ITEM_FDO: InterfaceName<'static> =
     InterfaceName::from_static_str_unchecked("org.freedesktop.StatusNotifierItem");
 
 const PROP_CATEGORY: MemberName<'static> = MemberName::from_static_str_unchecked("Category");
@@ -94,13 +99,38 @@ const PROP_ATTENTION_MOVIE_NAME: MemberName<'static> =
 const PROP_TOOL_TIP: MemberName<'static> = MemberName::from_static_str_unchecked("ToolTip");
 const PROP_MENU: MemberName<'static> = MemberName::from_static_str_unchecked("Menu");
 const PROP_ITEM_IS_MENU: MemberName<'static> = MemberName::from_static_str_unchecked("ItemIsMenu");
+const PROP_LABEL: MemberName<'static> = MemberName::from_static_str_unchecked("XAyatanaLabel");
+const PROP_LABEL_GUIDE: MemberName<'static> =
+    MemberName::from_static_str_unchecked("XAyatanaLabelGuide");
 
 const ACTIVATE: MemberName<'static> = MemberName::from_static_str_unchecked("Activate");
 const SECONDARY_ACTIVATE: MemberName<'static> =
     MemberName::from_static_str_unchecked("SecondaryActivate");
+const AYATANA_SECONDARY_ACTIVATE: MemberName<'static> =
+    MemberName::from_static_str_unchecked("XAyatanaSecondaryActivate");
+const PROVIDE_XDG_ACTIVATION_TOKEN: MemberName<'static> =
+    MemberName::from_static_str_unchecked("ProvideXdgActivationToken");
+const CONTEXT_MENU: MemberName<'static> = MemberName::from_static_str_unchecked("ContextMenu");
 const SCROLL: MemberName<'static> = MemberName::from_static_str_unchecked("Scroll");
 const EVENT: MemberName<'static> = MemberName::from_static_str_unchecked("Event");
+const EVENT_GROUP: MemberName<'static> = MemberName::from_static_str_unchecked("EventGroup");
 const ABOUT_TO_SHOW: MemberName<'static> = MemberName::from_static_str_unchecked("AboutToShow");
+const ABOUT_TO_SHOW_GROUP: MemberName<'static> =
+    MemberName::from_static_str_unchecked("AboutToShowGroup");
+
+/// The time that items have to respond before they are announced with the properties
+/// that have been retrieved so far.
+pub(super) const FETCH_TIMEOUT: Duration = Duration::from_secs(5);
+/// The initial delay before the properties of slow items are retrieved again.
+const REQUERY_DELAY: Duration = Duration::from_secs(5);
+const MAX_REQUERY_DELAY: Duration = Duration::from_secs(60);
+/// The maximum number of submenus that are prepared with a single `AboutToShowGroup`.
+const MAX_PREFETCHED_MENUS: usize = 32;
+
+const GET_CONNECTION_UNIX_PROCESS_ID: MemberName<'static> =
+    MemberName::from_static_str_unchecked("GetConnectionUnixProcessID");
+
+const UNKNOWN_METHOD: &str = "org.freedesktop.DBus.Error.UnknownMethod";
 
 const SIG_NEW_TITLE: MemberName<'static> = MemberName::from_static_str_unchecked("NewTitle");
 const SIG_NEW_ICON: MemberName<'static> = MemberName::from_static_str_unchecked("NewIcon");
@@ -110,6 +140,15 @@ const SIG_NEW_OVERLAY_ICON: MemberName<'static> =
     MemberName::from_static_str_unchecked("NewOverlayIcon");
 const SIG_NEW_TOOL_TIP: MemberName<'static> = MemberName::from_static_str_unchecked("NewToolTip");
 const SIG_NEW_STATUS: MemberName<'static> = MemberName::from_static_str_unchecked("NewStatus");
+const SIG_NEW_ICON_THEME_PATH: MemberName<'static> =
+    MemberName::from_static_str_unchecked("NewIconThemePath");
+const SIG_NEW_LABEL: MemberName<'static> =
+    MemberName::from_static_str_unchecked("XAyatanaNewLabel");
+
+const DBUS_PROPERTIES: InterfaceName<'static> =
+    InterfaceName::from_static_str_unchecked("org.freedesktop.DBus.Properties");
+const SIG_PROPERTIES_CHANGED: MemberName<'static> =
+    MemberName::from_static_str_unchecked("PropertiesChanged");
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum MutableProperty {
@@ -119,6 +158,11 @@ pub enum MutableProperty {
     OverlayIcon,
     ToolTip,
     Status,
+    IconThemePath,
+    Category,
+    Id,
+    ItemIsMenu,
+    Label,
 }
 
 pub trait SniItemOwner: Send + Sync {
@@ -165,6 +209,12 @@ pub struct SniItemProperties {
     pub attention_movie_name: Option<Arc<String>>,
     pub tooltip: Option<Arc<Tooltip>>,
     pub is_menu: bool,
+    /// The text that libayatana-appindicator items display next to their icon.
+    pub label: Option<Arc<String>>,
+    /// A string that is at least as wide as any label the item will display.
+    pub label_guide: Option<Arc<String>>,
+    /// The file name of the executable of the process that owns the item.
+    pub executable: Option<Arc<String>>,
 }
 
 #[derive(Copy, Clone, Eq, PartialEq)]
@@ -179,7 +229,10 @@ pub struct SniItemId(u64);
 
 pub struct SniItem {
     id: SniItemId,
+    /// The unique name of the connection that owns the item.
     destination: BusName<'static>,
+    /// The bus name that the item was first registered with.
+    bus_name: String,
     interface: &'static InterfaceName<'static>,
     path: ObjectPath<'static>,
     host: Arc<Host>,
@@ -195,29 +248,101 @@ impl SniItem {
         self.id
     }
 
+    pub fn bus_name(&self) -> &str {
+        &self.bus_name
+    }
+
     pub fn properties(&self) -> SniItemProperties {
         self.properties.lock().clone()
     }
 
-    fn activate_(&self, member: MemberName, cb: impl FnOnce(bool) + Send + 'static) -> Call {
+    pub(super) fn remove(&self) {
+        *self.status.lock() = ItemStatus::Removed;
+        if let Some(owner) = self.owner.swap(None) {
+            owner.removed();
+        }
+        self.signal_handlers.lock().clear();
+        self.menu.lock().take();
+    }
+
+    fn activate_(
+        &self,
+        member: MemberName,
+        x: i32,
+        y: i32,
+        cb: impl FnOnce(bool) + Send + 'static,
+    ) -> Call {
         self.host.dbus.call_async(
             &self.destination,
             self.interface,
             &self.path,
             member,
-            &(0i32, 0i32),
+            &(x, y),
             move |res: Result<(), _>| {
                 cb(res.is_ok());
             },
         )
     }
 
-    pub fn activate(&self, cb: impl FnOnce(bool) + Send + 'static) -> Call {
-        self.activate_(ACTIVATE, cb)
+    pub fn activate(&self, x: i32, y: i32, cb: impl FnOnce(bool) + Send + 'static) -> Call {
+        self.activate_(ACTIVATE, x, y, cb)
     }
 
-    pub fn secondary_activate(&self, cb: impl FnOnce(bool) + Send + 'static) -> Call {
-        self.activate_(SECONDARY_ACTIVATE, cb)
+    /// Performs a secondary activation, e.g. after a middle click.
+    ///
+    /// libayatana-appindicator items, which always export `XAyatanaLabel`, are
+    /// activated with `XAyatanaSecondaryActivate`.
+    pub fn secondary_activate(
+        self: &Arc<Self>,
+        x: i32,
+        y: i32,
+        cb: impl FnOnce(bool) + Send + 'static,
+    ) -> Call {
+        if self.properties.lock().label.is_none() {
+            return self.activate_(SECONDARY_ACTIVATE, x, y, cb);
+        }
+        let item = self.clone();
+        self.host.dbus.call_async(
+            &self.destination,
+            self.interface,
+            &self.path,
+            AYATANA_SECONDARY_ACTIVATE,
+            &0u32,
+            move |res: Result<(), _>| match res {
+                Err(bussy::Error::ErrorReply(name, _)) if name == UNKNOWN_METHOD => {
+                    item.activate_(SECONDARY_ACTIVATE, x, y, cb).detach();
+                }
+                res => cb(res.is_ok()),
+            },
+        )
+    }
+
+    /// Sends an activation token that the item can use in its next activation.
+    ///
+    /// Items that do not implement this method ignore it.
+    pub fn provide_xdg_activation_token(&self, token: &str) {
+        self.host.dbus.call_no_reply(
+            &self.destination,
+            self.interface,
+            &self.path,
+            PROVIDE_XDG_ACTIVATION_TOKEN,
+            &token,
+        )
+    }
+
+    pub fn context_menu(&self, x: i32, y: i32) {
+        self.host.dbus.call_no_reply(
+            &self.destination,
+            self.interface,
+            &self.path,
+            CONTEXT_MENU,
+            &(x, y),
+        )
+    }
+
+    /// Returns whether the item exports a DBusMenu.
+    pub fn has_menu(&self) -> bool {
+        self.menu.lock().is_some()
     }
 
     pub fn scroll(&self, delta: i32, axis: Axis) {
@@ -235,27 +360,126 @@ impl SniItem {
         )
     }
 
-    fn menu_event(&self, event: &str, menu_id: i32) {
-        let menu = self.menu.lock();
-        let Some(menu) = &*menu else {
+    fn menu_events(self: &Arc<Self>, events: &[(i32, &'static str)]) {
+        let mut menu = self.menu.lock();
+        let Some(menu) = &mut *menu else {
             return;
         };
+        if events.contains(&(0, "closed")) {
+            menu.prefetched.clear();
+        }
         let now = UNIX_EPOCH.elapsed().unwrap_or_default().as_secs() as u32;
-        self.host.dbus.call_no_reply(
-            &self.destination,
-            DBUS_MENU,
-            &menu.path,
-            EVENT,
-            &(menu_id, event, Value::U8(0), now),
-        )
+        if events.len() > 1 && menu.supports_groups {
+            let group: Vec<_> = events
+                .iter()
+                .map(|&(menu_id, event)| (menu_id, event, Value::U8(0), now))
+                .collect();
+            let item = self.clone();
+            let events = events.to_vec();
+            self.host
+                .dbus
+                .call_async(
+                    &self.destination,
+                    DBUS_MENU,
+                    &menu.path,
+                    EVENT_GROUP,
+                    &group,
+                    move |res: Result<Vec<i32>, _>| {
+                        if let Err(bussy::Error::ErrorReply(name, _)) = &res {
+                            if name == UNKNOWN_METHOD {
+                                if let Some(menu) = &mut *item.menu.lock() {
+                                    menu.supports_groups = false;
+                                }
+                                item.menu_events(&events);
+                            }
+                        }
+                    },
+                )
+                .detach();
+            return;
+        }
+        for &(menu_id, event) in events {
+            self.host.dbus.call_no_reply(
+                &self.destination,
+                DBUS_MENU,
+                &menu.path,
+                EVENT,
+                &(menu_id, event, Value::U8(0), now),
+            )
+        }
     }
 
-    pub fn menu_hovered(&self, menu_id: i32) {
-        self.menu_event("hovered", menu_id);
+    pub fn menu_hovered(self: &Arc<Self>, menu_id: i32) {
+        self.menu_events(&[(menu_id, "hovered")]);
     }
 
-    pub fn menu_clicked(&self, menu_id: i32) {
-        self.menu_event("clicked", menu_id);
+    pub fn menu_clicked(self: &Arc<Self>, menu_id: i32) {
+        self.menu_events(&[(menu_id, "clicked")]);
+    }
+
+    pub fn menu_opened(self: &Arc<Self>, menu_id: i32) {
+        self.menu_events(&[(menu_id, "opened")]);
+    }
+
+    pub fn menus_closed(self: &Arc<Self>, menu_ids: &[i32]) {
+        let events: Vec<_> = menu_ids.iter().map(|&id| (id, "closed")).collect();
+        self.menu_events(&events);
+    }
+
+    /// Notifies the application that the submenus `menu_ids` of `parent` might be shown
+    /// soon.
+    ///
+    /// Opening one of these submenus afterwards does not require another round trip.
+    /// Submenus that have already been prepared are skipped and at most
+    /// `MAX_PREFETCHED_MENUS` are prepared at once.
+    pub fn prefetch_menus(self: &Arc<Self>, parent: i32, mut menu_ids: Vec<i32>) {
+        let menu = self.menu.lock();
+        let Some(menu) = &*menu else {
+            return;
+        };
+        menu_ids.retain(|id| !menu.prefetched.contains(id));
+        menu_ids.truncate(MAX_PREFETCHED_MENUS);
+        if !menu.supports_groups || menu_ids.is_empty() {
+            return;
+        }
+        let item = self.clone();
+        self.host
+            .dbus
+            .call_async(
+                &self.destination,
+                DBUS_MENU,
+                &menu.path,
+                ABOUT_TO_SHOW_GROUP,
+                &menu_ids.clone(),
+                move |res: Result<(Vec<i32>, Vec<i32>), _>| {
+                    let mut menu = item.menu.lock();
+                    let Some(menu) = &mut *menu else {
+                        return;
+                    };
+                    let (updates_needed, id_errors) = match res {
+                        Ok(res) => res,
+                        Err(bussy::Error::ErrorReply(name, _)) if name == UNKNOWN_METHOD => {
+                            menu.supports_groups = false;
+                            return;
+                        }
+                        Err(_) => return,
+                    };
+                    let mut prefetched = menu_ids;
+                    prefetched.retain(|id| !id_errors.contains(id));
+                    if updates_needed.is_empty() {
+                        menu.prefetched.extend(prefetched);
+                        return;
+                    }
+                    let i2 = item.clone();
+                    let callback = Box::new(move || {
+                        if let Some(menu) = &mut *i2.menu.lock() {
+                            menu.prefetched.extend(prefetched);
+                        }
+                    });
+                    menu.update_layout(&item, parent, Some(callback));
+                },
+            )
+            .detach();
     }
 
     pub fn open_menu(
@@ -263,10 +487,13 @@ impl SniItem {
         menu_id: i32,
         callback: Box<dyn FnOnce() + Send + Sync>,
     ) -> Option<Call> {
-        let menu = self.menu.lock();
-        let Some(menu) = &*menu else {
+        let mut menu = self.menu.lock();
+        let Some(menu) = &mut *menu else {
             return None;
         };
+        if menu.prefetched.remove(&menu_id) {
+            return None;
+        }
         let item = self.clone();
         let call = self.host.dbus.call_async(
             &self.destination,
@@ -303,7 +530,235 @@ impl SniItem {
         self.host
             .dbus
             .get_property(&self.destination, self.interface, &self.path, name)
+            .with_timeout(Some(FETCH_TIMEOUT))
     }
+
+    /// Retrieves the properties and the menu of the item.
+    ///
+    /// Properties that are not retrieved in time keep their previous values. Returns
+    /// whether any call timed out and the menu if it has been created.
+    async fn fetch(self: &Arc<Self>) -> (bool, Option<SniMenuDelta>) {
+        let mut timed_out = false;
+        macro_rules! get {
+            ($($name:ident, $member:ident, $ty:ty;)*) => {
+                $(
+                    let $name = self.get_prop::<$ty>($member);
+                )*
+                $(
+                    let $name = $name.await;
+                )*
+                {
+                    let mut props = self.properties.lock();
+                    $(
+                        match $name {
+                            Err(bussy::Error::Timeout) => timed_out = true,
+                            res => props.$name = res.ok().map(|v| v.into()),
+                        }
+                    )*
+                }
+            };
+        }
+        let menu = self.get_prop::<OwnedObjectPath>(PROP_MENU);
+        let is_menu = self.get_prop::<bool>(PROP_ITEM_IS_MENU);
+        get! {
+            category, PROP_CATEGORY, String;
+            id, PROP_ID, String;
+            title, PROP_TITLE, String;
+            status, PROP_STATUS, String;
+            icon_name, PROP_ICON_NAME, String;
+            icon_theme_path, PROP_ICON_THEME_PATH, String;
+            icon, PROP_ICON_PIXMAP, Vec<IconPixmap>;
+            attention_icon_name, PROP_ATTENTION_ICON_NAME, String;
+            attention_movie_name, PROP_ATTENTION_MOVIE_NAME, String;
+            attention_icon, PROP_ATTENTION_ICON_PIXMAP, Vec<IconPixmap>;
+            overlay_icon_name, PROP_OVERLAY_ICON_NAME, String;
+            overlay_icon, PROP_OVERLAY_ICON_PIXMAP, Vec<IconPixmap>;
+            tooltip, PROP_TOOL_TIP, Tooltip;
+            label, PROP_LABEL, String;
+            label_guide, PROP_LABEL_GUIDE, String;
+        }
+        match is_menu.await {
+            Err(bussy::Error::Timeout) => timed_out = true,
+            res => self.properties.lock().is_menu = matches!(res, Ok(true)),
+        }
+        let menu_path = match menu.await {
+            Ok(path) => Some(path),
+            Err(e) => {
+                timed_out |= matches!(e, bussy::Error::Timeout);
+                None
+            }
+        };
+        let mut menu_delta = None;
+        if let Some(path) = menu_path {
+            if self.menu.lock().is_none() {
+                match Menu::new(self, &self.host.dbus, &self.destination, &path).await {
+                    Ok(menu) => {
+                        menu_delta = Some(menu.tree.clone().into());
+                        *self.menu.lock() = Some(menu);
+                    }
+                    Err(e) => timed_out |= matches!(e, bussy::Error::Timeout),
+                }
+            }
+        }
+        (timed_out, menu_delta)
+    }
+
+    /// Determines the executable of the process that owns the item.
+    async fn fetch_executable(&self) {
+        let pid = self
+            .host
+            .dbus
+            .call::<u32>(
+                DBUS_NAME,
+                DBUS_INTERFACE,
+                DBUS_PATH,
+                GET_CONNECTION_UNIX_PROCESS_ID,
+                &self.destination.as_str(),
+            )
+            .with_timeout(Some(FETCH_TIMEOUT));
+        let Ok(pid) = pid.await else {
+            return;
+        };
+        let exe = std::fs::read_link(format!("/proc/{pid}/exe"))
+            .ok()
+            .and_then(|p| Some(p.file_name()?.to_str()?.to_string()));
+        let exe = exe.or_else(|| {
+            let comm = std::fs::read_to_string(format!("/proc/{pid}/comm")).ok()?;
+            Some(comm.trim_end().to_string())
+        });
+        self.properties.lock().executable = exe.map(Arc::new);
+    }
+
+    /// Retrieves the properties and the menu again until the item responds in time.
+    async fn requery(self: &Arc<Self>) {
+        let mut delay = REQUERY_DELAY;
+        loop {
+            tokio::time::sleep(delay).await;
+            if *self.status.lock() == ItemStatus::Removed {
+                return;
+            }
+            let (timed_out, menu_delta) = self.fetch().await;
+            self.notify(&[
+                MutableProperty::Title,
+                MutableProperty::Icon,
+                MutableProperty::AttentionIcon,
+                MutableProperty::OverlayIcon,
+                MutableProperty::ToolTip,
+                MutableProperty::Status,
+                MutableProperty::IconThemePath,
+                MutableProperty::Category,
+                MutableProperty::Id,
+                MutableProperty::ItemIsMenu,
+                MutableProperty::Label,
+            ]);
+            if let Some(owner) = &*self.owner.load() {
+                if let Some(delta) = menu_delta {
+                    owner.menu_changed(delta);
+                }
+            }
+            if !timed_out {
+                return;
+            }
+            delay = (delay * 2).min(MAX_REQUERY_DELAY);
+        }
+    }
+
+    fn notify(&self, props: &[MutableProperty]) {
+        if let Some(owner) = &*self.owner.load() {
+            for &prop in props {
+                owner.property_changed(prop);
+            }
+        }
+    }
+
+    /// Handles a `PropertiesChanged` signal of the item.
+    ///
+    /// Properties whose values are included in the signal are stored directly.
+    /// Invalidated properties are retrieved again.
+    fn handle_properties_changed(
+        self: &Arc<Self>,
+        changed: HashMap<String, OwnedValue>,
+        invalidated: Vec<String>,
+    ) {
+        let mut mutable = vec![];
+        {
+            let props = &mut *self.properties.lock();
+            for (name, value) in changed {
+                if let Some(prop) = set_property(props, &name, Some(value)) {
+                    if !mutable.contains(&prop) {
+                        mutable.push(prop);
+                    }
+                }
+            }
+        }
+        self.notify(&mutable);
+        if invalidated.is_empty() {
+            return;
+        }
+        let item = self.clone();
+        tokio::spawn(async move {
+            let calls: Vec<_> = invalidated
+                .iter()
+                .filter_map(|name| {
+                    let member = MemberName::try_from(name.as_str()).ok()?;
+                    Some((name, item.get_prop::<OwnedValue>(member)))
+                })
+                .collect();
+            let mut mutable = vec![];
+            for (name, call) in calls {
+                let value = call.await.ok();
+                let props = &mut *item.properties.lock();
+                if let Some(prop) = set_property(props, name, value) {
+                    if !mutable.contains(&prop) {
+                        mutable.push(prop);
+                    }
+                }
+            }
+            item.notify(&mutable);
+        });
+    }
+}
+
+/// Stores the value of the property `name`. A value of `None` unsets the property.
+///
+/// Returns the property that has changed or `None` if the property is not tracked.
+fn set_property(
+    props: &mut SniItemProperties,
+    name: &str,
+    value: Option<OwnedValue>,
+) -> Option<MutableProperty> {
+    macro_rules! set {
+        ($($member:ident, $field:ident, $ty:ty, $mutable:ident;)*) => {
+            $(
+                if name == $member.as_str() {
+                    props.$field = value.and_then(|v| <$ty>::try_from(v).ok()).map(|v| v.into());
+                    return Some(MutableProperty::$mutable);
+                }
+            )*
+        };
+    }
+    set! {
+        PROP_CATEGORY, category, String, Category;
+        PROP_ID, id, String, Id;
+        PROP_TITLE, title, String, Title;
+        PROP_STATUS, status, String, Status;
+        PROP_ICON_NAME, icon_name, String, Icon;
+        PROP_ICON_THEME_PATH, icon_theme_path, String, IconThemePath;
+        PROP_ICON_PIXMAP, icon, Vec<IconPixmap>, Icon;
+        PROP_ATTENTION_ICON_NAME, attention_icon_name, String, AttentionIcon;
+        PROP_ATTENTION_MOVIE_NAME, attention_movie_name, String, AttentionIcon;
+        PROP_ATTENTION_ICON_PIXMAP, attention_icon, Vec<IconPixmap>, AttentionIcon;
+        PROP_OVERLAY_ICON_NAME, overlay_icon_name, String, OverlayIcon;
+        PROP_OVERLAY_ICON_PIXMAP, overlay_icon, Vec<IconPixmap>, OverlayIcon;
+        PROP_TOOL_TIP, tooltip, Tooltip, ToolTip;
+        PROP_LABEL, label, String, Label;
+        PROP_LABEL_GUIDE, label_guide, String, Label;
+    }
+    if name == PROP_ITEM_IS_MENU.as_str() {
+        props.is_menu = matches!(value.map(bool::try_from), Some(Ok(true)));
+        return Some(MutableProperty::ItemIsMenu);
+    }
+    None
 }
 
 impl Debug for SniItem {
@@ -316,20 +771,14 @@ impl Debug for SniItem {
 }
 
 impl Host {
-    pub fn handle_new_item(self: &Arc<Self>, fdo: bool, id: &str) {
-        if self.items.lock().contains_key(id) {
-            return;
-        }
-        let (destination, path) = match id.find("/") {
-            None => (id, "/StatusNotifierItem"),
-            Some(p) => (&id[..p], &id[p..]),
-        };
-        let Ok(destination) = BusName::try_from(destination.to_string()) else {
-            return;
-        };
-        let Ok(path) = ObjectPath::try_from(path.to_string()) else {
-            return;
-        };
+    pub(super) fn create_item(
+        self: &Arc<Self>,
+        fdo: bool,
+        key: ItemKey,
+        destination: UniqueName<'static>,
+        bus_name: String,
+        path: ObjectPath<'static>,
+    ) {
         let interface = match fdo {
             true => &ITEM_FDO,
             false => &ITEM_KDE,
@@ -341,7 +790,8 @@ impl Host {
                 *ids += 1;
                 SniItemId(*ids)
             },
-            destination,
+            destination: BusName::Unique(destination),
+            bus_name,
             interface,
             path,
             host: self.clone(),
@@ -355,6 +805,10 @@ impl Host {
         macro_rules! handle_signal {
             ($sig:ident, $sty:ty, $mutable:ident, [$($prop:ident, $field:ident, $ty:ty;)+]) => {{
                 let i1 = item.clone();
+                let coalescer = Arc::new(Coalescer::new(format!(
+                    "{} of {}{}",
+                    $sig, item.destination, item.path,
+                )));
                 let handler = self.dbus.handle_messages(
                     MatchRuleBuilder::default()
                         .interface(interface)
@@ -363,30 +817,41 @@ impl Host {
                         .path(&item.path)
                         .build(),
                     move |_: $sty| {
+                        if !coalescer.signal() {
+                            return;
+                        }
                         let i2 = i1.clone();
+                        let coalescer = coalescer.clone();
                         tokio::spawn(async move {
-                            $(
-                                let $field =
-                                    i2.host
-                                        .dbus
-                                        .get_property::<$ty>(
-                                            &i2.destination,
-                                            i2.interface,
-                                            &i2.path,
-                                            $prop,
-                                        );
-                            )+
-                            $(
-                                let $field = $field.await.ok().map(|v| v.into());
-                            )+
-                            {
-                                let props = &mut *i2.properties.lock();
+                            loop {
+                                let delay = coalescer.delay(settings::get().update_interval);
+                                tokio::time::sleep(delay).await;
                                 $(
-                                    props.$field = $field;
+                                    let $field =
+                                        i2.host
+                                            .dbus
+                                            .get_property::<$ty>(
+                                                &i2.destination,
+                                                i2.interface,
+                                                &i2.path,
+                                                $prop,
+                                            );
                                 )+
-                            }
-                            if let Some(owner) = &*i2.owner.load() {
-                                owner.property_changed(MutableProperty::$mutable);
+                                $(
+                                    let $field = $field.await.ok().map(|v| v.into());
+                                )+
+                                {
+                                    let props = &mut *i2.properties.lock();
+                                    $(
+                                        props.$field = $field;
+                                    )+
+                                }
+                                if let Some(owner) = &*i2.owner.load() {
+                                    owner.property_changed(MutableProperty::$mutable);
+                                }
+                                if !coalescer.complete() {
+                                    break;
+                                }
                             }
                         });
                     },
@@ -404,6 +869,7 @@ impl Host {
         handle_signal!(SIG_NEW_ATTENTION_ICON, (), AttentionIcon, [
             PROP_ATTENTION_ICON_PIXMAP, attention_icon, Vec<IconPixmap>;
             PROP_ATTENTION_ICON_NAME, attention_icon_name, String;
+            PROP_ATTENTION_MOVIE_NAME, attention_movie_name, String;
         ]);
         handle_signal!(SIG_NEW_OVERLAY_ICON, (), OverlayIcon, [
             PROP_OVERLAY_ICON_PIXMAP, overlay_icon, Vec<IconPixmap>;
@@ -415,52 +881,38 @@ impl Host {
         handle_signal!(SIG_NEW_STATUS, String, Status, [
             PROP_STATUS, status, String;
         ]);
+        handle_signal!(SIG_NEW_ICON_THEME_PATH, String, IconThemePath, [
+            PROP_ICON_THEME_PATH, icon_theme_path, String;
+        ]);
+        handle_signal!(SIG_NEW_LABEL, (String, String), Label, [
+            PROP_LABEL, label, String;
+            PROP_LABEL_GUIDE, label_guide, String;
+        ]);
+        {
+            let i1 = item.clone();
+            let handler = self.dbus.handle_messages(
+                MatchRuleBuilder::default()
+                    .interface(DBUS_PROPERTIES)
+                    .member(SIG_PROPERTIES_CHANGED)
+                    .sender(&item.destination)
+                    .path(&item.path)
+                    .build(),
+                move |(name, changed, invalidated): (
+                    String,
+                    HashMap<String, OwnedValue>,
+                    Vec<String>,
+                )| {
+                    if name == i1.interface.as_str() {
+                        i1.handle_properties_changed(changed, invalidated);
+                    }
+                },
+            );
+            signal_handlers.push(handler);
+        }
         *item.signal_handlers.lock() = signal_handlers;
-        self.items.lock().insert(id.to_string(), item.clone());
+        self.data.lock().items.insert(key, item.clone());
         tokio::spawn(async move {
-            macro_rules! get {
-                ($($name:ident, $member:ident, $ty:ty;)*) => {
-                    $(
-                        let $name = item.get_prop::<$ty>($member);
-                    )*
-                    $(
-                        let $name = $name.await.ok();
-                    )*
-                    {
-                        let mut props = item.properties.lock();
-                        $(
-                            props.$name = $name.map(|v| v.into());
-                        )*
-                    }
-                };
-            }
-            let menu = item.get_prop::<OwnedObjectPath>(PROP_MENU);
-            let is_menu = item.get_prop::<bool>(PROP_ITEM_IS_MENU);
-            get! {
-                category, PROP_CATEGORY, String;
-                id, PROP_ID, String;
-                title, PROP_TITLE, String;
-                status, PROP_STATUS, String;
-                icon_name, PROP_ICON_NAME, String;
-                icon_theme_path, PROP_ICON_THEME_PATH, String;
-                icon, PROP_ICON_PIXMAP, Vec<IconPixmap>;
-                attention_icon_name, PROP_ATTENTION_ICON_NAME, String;
-                attention_movie_name, PROP_ATTENTION_MOVIE_NAME, String;
-                attention_icon, PROP_ATTENTION_ICON_PIXMAP, Vec<IconPixmap>;
-                overlay_icon_name, PROP_OVERLAY_ICON_NAME, String;
-                overlay_icon, PROP_OVERLAY_ICON_PIXMAP, Vec<IconPixmap>;
-                tooltip, PROP_TOOL_TIP, Tooltip;
-            }
-            item.properties.lock().is_menu = matches!(is_menu.await, Ok(true));
-            let menu_path: Option<OwnedObjectPath> = menu.await.ok();
-            let mut menu_delta = None;
-            if let Some(path) = menu_path {
-                let menu = Menu::new(&item, &item.host.dbus, &item.destination, &path).await;
-                if let Some(menu) = &menu {
-                    menu_delta = Some(menu.tree.clone().into());
-                }
-                *item.menu.lock() = menu;
-            }
+            let (_, (timed_out, menu_delta)) = tokio::join!(item.fetch_executable(), item.fetch());
             {
                 let mut status = item.status.lock();
                 if *status == ItemStatus::New {
@@ -470,21 +922,17 @@ impl Host {
                             owner.menu_changed(delta);
                         }
                     }
+                    *status = ItemStatus::Announced;
                 }
-                *status = ItemStatus::Announced;
+            }
+            if timed_out {
+                log::warn!(
+                    "{}{} did not respond in time. Retrying later",
+                    item.destination,
+                    item.path,
+                );
+                item.requery().await;
             }
         });
     }
-
-    pub fn handle_removed_item(self: &Arc<Self>, _fdo: bool, id: &str) {
-        let Some(item) = self.items.lock().remove(id) else {
-            return;
-        };
-        *item.status.lock() = ItemStatus::Removed;
-        if let Some(owner) = item.owner.swap(None) {
-            owner.removed();
-        }
-        item.signal_handlers.lock().clear();
-        item.menu.lock().take();
-    }
 }
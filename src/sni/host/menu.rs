@@ -1,6 +1,12 @@
 use {
-    crate::sni::host::item::SniItem,
-    ahash::{AHashMap, HashMap},
+    crate::{
+        settings,
+        sni::host::{
+            coalesce::Coalescer,
+            item::{SniItem, FETCH_TIMEOUT},
+        },
+    },
+    ahash::{AHashMap, AHashSet, HashMap},
     bussy::{Call, Connection, MatchRuleBuilder, SignalHandler},
     indexmap::IndexMap,
     isnt::std_1::collections::IsntHashMapExt,
@@ -28,6 +34,14 @@ pub struct Menu {
     pub tree: MenuTree,
     pub next_layout_update: u64,
     pub layout_updates: AHashMap<u64, Call>,
+    /// Whether the peer implements `AboutToShowGroup` and `EventGroup`.
+    pub supports_groups: bool,
+    /// Submenus that have been prepared with `AboutToShowGroup`.
+    pub prefetched: AHashSet<i32>,
+    /// Coalesces `LayoutUpdated` signals.
+    pub layout_coalescer: Arc<Coalescer>,
+    /// The submenu whose layout has to be fetched by the next coalesced update.
+    pub dirty_layout: Option<i32>,
     pub _signals: Vec<SignalHandler>,
 }
 
@@ -43,7 +57,7 @@ impl Menu {
         dbus: &Arc<Connection>,
         destination: &BusName<'static>,
         path: &ObjectPath<'static>,
-    ) -> Option<Self> {
+    ) -> Result<Self, bussy::Error> {
         let (revision, value) = dbus
             .call::<(u32, MenuLayoutValue)>(
                 destination,
@@ -52,8 +66,8 @@ impl Menu {
                 GET_LAYOUT,
                 &(0i32, -1i32, Vec::<String>::new()),
             )
-            .await
-            .ok()?;
+            .with_timeout(Some(FETCH_TIMEOUT))
+            .await?;
         let build = |member| {
             MatchRuleBuilder::default()
                 .msg_type(zbus::message::Type::Signal)
@@ -70,7 +84,7 @@ impl Menu {
                 if let Some(menu) = &mut *i1.menu.lock() {
                     if menu.revision < revision {
                         menu.revision = revision;
-                        menu.update_layout(&i1, menu_id, None);
+                        menu.handle_layout_updated(&i1, menu_id);
                     }
                 }
             },
@@ -96,7 +110,7 @@ impl Menu {
             },
         );
         let tree = value.parse_tree();
-        Some(Self {
+        Ok(Self {
             dbus: dbus.clone(),
             destination: destination.clone(),
             path: path.clone(),
@@ -104,6 +118,12 @@ impl Menu {
             tree,
             next_layout_update: 0,
             layout_updates: Default::default(),
+            supports_groups: true,
+            prefetched: Default::default(),
+            layout_coalescer: Arc::new(Coalescer::new(format!(
+                "{LAYOUT_UPDATED} of {destination}{path}"
+            ))),
+            dirty_layout: None,
             _signals: vec![s1, s2],
         })
     }
@@ -128,6 +148,10 @@ impl Menu {
                 if let Some(m) = &mut *menu {
                     m.layout_updates.remove(&id);
                     let Ok((_, v)) = res else {
+                        drop(menu);
+                        if let Some(cb) = callback {
+                            cb();
+                        }
                         return;
                     };
                     let tree = v.parse_tree();
@@ -147,6 +171,47 @@ impl Menu {
         self.layout_updates.insert(id, call);
     }
 
+    /// Fetches the layout of the submenu `menu_id` after it has changed.
+    ///
+    /// While a fetch is in flight, further changes are combined into a single follow-up
+    /// fetch.
+    fn handle_layout_updated(&mut self, item: &Arc<SniItem>, menu_id: i32) {
+        // The application might have to prepare the submenu again.
+        match menu_id {
+            0 => self.prefetched.clear(),
+            _ => _ = self.prefetched.remove(&menu_id),
+        }
+        self.dirty_layout = match self.dirty_layout {
+            Some(id) if id != menu_id => Some(0),
+            _ => Some(menu_id),
+        };
+        if self.layout_coalescer.signal() {
+            Self::start_coalesced_update(item, self.layout_coalescer.clone());
+        }
+    }
+
+    fn start_coalesced_update(item: &Arc<SniItem>, coalescer: Arc<Coalescer>) {
+        let item = item.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(coalescer.delay(settings::get().update_interval)).await;
+            let mut menu = item.menu.lock();
+            let Some(m) = &mut *menu else {
+                return;
+            };
+            let menu_id = m.dirty_layout.take().unwrap_or(0);
+            let i2 = item.clone();
+            m.update_layout(
+                &item,
+                menu_id,
+                Some(Box::new(move || {
+                    if coalescer.complete() {
+                        Self::start_coalesced_update(&i2, coalescer);
+                    }
+                })),
+            );
+        });
+    }
+
     pub fn update_properties(
         &mut self,
         item: &Arc<SniItem>,
@@ -179,14 +244,16 @@ impl Menu {
 struct MenuProperties {
     pub menu_id: i32,
     pub separator: bool,
-    pub access_key: Option<char>,
+    pub access_key: Option<SniMenuAccessKey>,
     pub label: Arc<String>,
     pub enabled: bool,
     pub visible: bool,
     pub icon_name: Arc<String>,
     pub icon_png: Arc<Vec<u8>>,
     pub toggle_type: Option<SniMenuToggleType>,
-    pub toggle_state: bool,
+    pub toggle_state: SniMenuToggleState,
+    pub shortcut: Arc<Vec<Vec<String>>>,
+    pub disposition: SniMenuDisposition,
     pub children_display: bool,
 }
 
@@ -243,7 +310,25 @@ impl MenuProperties {
             };
         });
         get!(toggle_state, "toggle-state", i32, Default::default(), v, {
-            self.toggle_state = v == 1;
+            self.toggle_state = match v {
+                0 => SniMenuToggleState::Off,
+                1 => SniMenuToggleState::On,
+                _ => SniMenuToggleState::Indeterminate,
+            };
+        });
+        get!(shortcut, "shortcut", Array, Default::default(), v, {
+            match v.try_into() {
+                Ok(v) => self.shortcut = Arc::new(v),
+                _ => self.shortcut = Default::default(),
+            }
+        });
+        get!(disposition, "disposition", &str, Default::default(), v, {
+            self.disposition = match v {
+                "informative" => SniMenuDisposition::Informative,
+                "warning" => SniMenuDisposition::Warning,
+                "alert" => SniMenuDisposition::Alert,
+                _ => SniMenuDisposition::Normal,
+            };
         });
         get!(children_display, "children-display", &str, false, v, {
             self.children_display = v == "submenu"
@@ -262,6 +347,7 @@ impl MenuProperties {
                 };
                 let mut label = String::new();
                 let mut last_was_underscore = false;
+                self.access_key = None;
                 for c in s.chars() {
                     if c == '_' {
                         if last_was_underscore {
@@ -273,7 +359,12 @@ impl MenuProperties {
                         continue;
                     }
                     if last_was_underscore {
-                        self.access_key = Some(c);
+                        if self.access_key.is_none() {
+                            self.access_key = Some(SniMenuAccessKey {
+                                key: c,
+                                offset: label.len(),
+                            });
+                        }
                         last_was_underscore = false;
                     }
                     label.push(c);
@@ -284,17 +375,41 @@ impl MenuProperties {
     }
 }
 
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct SniMenuAccessKey {
+    pub key: char,
+    /// The byte offset of the key in the label.
+    pub offset: usize,
+}
+
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum SniMenuToggleType {
     Checkmark,
     Radio,
 }
 
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum SniMenuToggleState {
+    #[default]
+    Off,
+    On,
+    Indeterminate,
+}
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum SniMenuDisposition {
+    #[default]
+    Normal,
+    Informative,
+    Warning,
+    Alert,
+}
+
 #[derive(Debug, Deserialize, Type, Value, OwnedValue)]
-struct MenuLayoutValue {
-    menu_id: i32,
-    properties: HashMap<String, OwnedValue>,
-    children: Vec<OwnedValue>,
+pub struct MenuLayoutValue {
+    pub menu_id: i32,
+    pub properties: HashMap<String, OwnedValue>,
+    pub children: Vec<OwnedValue>,
 }
 
 #[derive(Debug, Clone)]
@@ -326,6 +441,8 @@ impl MenuTree {
         diff_prop!(icon_png);
         diff_prop!(toggle_type);
         diff_prop!(toggle_state);
+        diff_prop!(shortcut);
+        diff_prop!(disposition);
         diff_prop!(children_display);
         any_props_differ.then_some(prop_delta)
     }
@@ -434,14 +551,16 @@ impl MenuTree {
 #[derive(Default, Clone, Debug, Eq, PartialEq)]
 pub struct SniMenuPropertiesDelta {
     pub separator: Option<bool>,
-    pub access_key: Option<Option<char>>,
+    pub access_key: Option<Option<SniMenuAccessKey>>,
     pub label: Option<Arc<String>>,
     pub enabled: Option<bool>,
     pub visible: Option<bool>,
     pub icon_name: Option<Arc<String>>,
     pub icon_png: Option<Arc<Vec<u8>>>,
     pub toggle_type: Option<Option<SniMenuToggleType>>,
-    pub toggle_state: Option<bool>,
+    pub toggle_state: Option<SniMenuToggleState>,
+    pub shortcut: Option<Arc<Vec<Vec<String>>>>,
+    pub disposition: Option<SniMenuDisposition>,
     pub children_display: Option<bool>,
 }
 
@@ -457,6 +576,8 @@ impl From<MenuProperties> for SniMenuPropertiesDelta {
             icon_png: Some(value.icon_png),
             toggle_type: Some(value.toggle_type),
             toggle_state: Some(value.toggle_state),
+            shortcut: Some(value.shortcut),
+            disposition: Some(value.disposition),
             children_display: Some(value.children_display),
         }
     }
@@ -500,7 +621,7 @@ impl MenuLayoutValue {
         }
     }
 
-    pub fn parse_properties(&self) -> MenuProperties {
+    fn parse_properties(&self) -> MenuProperties {
         let mut props = MenuProperties::default();
         props.apply_properties(&self.properties);
         props
@@ -1,5 +1,7 @@
 use {
-    bussy::{Connection, Object, PendingReply},
+    crate::sni::{watcher::owners::Owners, DBUS_INTERFACE, DBUS_NAME, DBUS_PATH, GET_NAME_OWNER},
+    bussy::{BitFlags, Connection, Object, PendingReply, RequestNameReply},
+    error_reporter::Report,
     isnt::std_1::primitive::IsntStrExt,
     parking_lot::Mutex,
     std::{collections::HashSet, sync::Arc},
@@ -9,6 +11,9 @@ use {
     },
 };
 
+mod owners;
+mod recover;
+
 pub const FDO_WATCHER_NAME: WellKnownName<'static> =
     WellKnownName::from_static_str_unchecked("org.freedesktop.StatusNotifierWatcher");
 pub static FDO_WATCHER_INTERFACE: InterfaceName<'static> =
@@ -38,7 +43,9 @@ pub const REGISTERED_STATUS_NOTIFIER_ITEMS: MemberName<'static> =
 
 #[derive(Default)]
 struct DataMut {
-    items: HashSet<String>,
+    /// Whether this process owns the watcher name of the protocol.
+    active: bool,
+    items: Owners,
     hosts: HashSet<String>,
 }
 
@@ -57,7 +64,12 @@ impl Data {
         }
     }
 
-    fn register_status_notifier_item(&self, fdo: bool, service_or_path: &str, pr: &PendingReply) {
+    fn register_status_notifier_item(
+        self: &Arc<Self>,
+        fdo: bool,
+        service_or_path: &str,
+        pr: &PendingReply,
+    ) {
         let item_string;
         let item = if fdo {
             service_or_path
@@ -74,21 +86,52 @@ impl Data {
                 &item_string
             }
         };
-        let new_item = {
+        self.add_item(fdo, item);
+    }
+
+    /// Adds the item and announces it once its owner has been resolved.
+    fn add_item(self: &Arc<Self>, fdo: bool, item: &str) {
+        {
             let mut data = self.data(fdo).lock();
-            data.items.insert(item.to_string())
-        };
-        if new_item {
-            let items: Vec<_> = self.data(fdo).lock().items.iter().cloned().collect();
-            let int = match fdo {
-                true => &FDO_WATCHER_INTERFACE,
-                false => &KDE_WATCHER_INTERFACE,
-            };
-            self.obj
-                .set_property(int, &REGISTERED_STATUS_NOTIFIER_ITEMS, items.clone());
-            self.dbus
-                .send_signal(int, &WATCHER_PATH, &STATUS_NOTIFIER_ITEM_REGISTERED, &item);
+            if !data.active || !data.items.insert(item) {
+                return;
+            }
         }
+        let w = self.clone();
+        let name = owners::bus_name(item);
+        let item = item.to_string();
+        self.dbus
+            .call_async(
+                DBUS_NAME,
+                DBUS_INTERFACE,
+                DBUS_PATH,
+                GET_NAME_OWNER,
+                &name,
+                move |res: Result<String, _>| w.handle_owner_resolved(fdo, &item, res.ok()),
+            )
+            .detach();
+    }
+
+    fn handle_owner_resolved(&self, fdo: bool, item: &str, owner: Option<String>) {
+        let items = {
+            let mut data = self.data(fdo).lock();
+            let Some(owner) = owner else {
+                data.items.remove_unresolved(item);
+                return;
+            };
+            if !data.items.resolve(item, &owner) {
+                return;
+            }
+            data.items.resolved()
+        };
+        let int = match fdo {
+            true => &FDO_WATCHER_INTERFACE,
+            false => &KDE_WATCHER_INTERFACE,
+        };
+        self.obj
+            .set_property(int, &REGISTERED_STATUS_NOTIFIER_ITEMS, items);
+        self.dbus
+            .send_signal(int, &WATCHER_PATH, &STATUS_NOTIFIER_ITEM_REGISTERED, &item);
     }
 
     pub fn register_status_notifier_host(&self, fdo: bool, service: &str) {
@@ -117,51 +160,25 @@ impl Data {
         if new_owner.is_not_empty() {
             return;
         }
-        if name == FDO_WATCHER_INTERFACE.as_str() {
-            self.dbus.request_name(FDO_WATCHER_NAME);
-        }
-        if name == KDE_WATCHER_INTERFACE.as_str() {
-            self.dbus.request_name(KDE_WATCHER_NAME);
-        }
-        {
-            let mut fdo = self.fdo.lock();
-            if fdo.items.remove(&name) {
-                self.dbus.send_signal(
-                    &FDO_WATCHER_INTERFACE,
-                    &WATCHER_PATH,
-                    &STATUS_NOTIFIER_ITEM_UNREGISTERED,
-                    &name,
-                );
-                self.obj.set_property(
-                    &FDO_WATCHER_INTERFACE,
-                    &REGISTERED_STATUS_NOTIFIER_ITEMS,
-                    fdo.items.iter().cloned().collect::<Vec<_>>(),
-                )
+        for fdo in [true, false] {
+            let int = match fdo {
+                true => &FDO_WATCHER_INTERFACE,
+                false => &KDE_WATCHER_INTERFACE,
+            };
+            let (removed, items) = {
+                let mut data = self.data(fdo).lock();
+                let removed = data.items.remove_name(&name);
+                (removed, data.items.resolved())
+            };
+            if removed.is_empty() {
+                continue;
             }
-        }
-        {
-            let mut kde = self.kde.lock();
-            let mut removed_any = false;
-            kde.items.retain(|item| {
-                let remove = item.starts_with(&name);
-                if remove {
-                    self.dbus.send_signal(
-                        &KDE_WATCHER_INTERFACE,
-                        &WATCHER_PATH,
-                        &STATUS_NOTIFIER_ITEM_UNREGISTERED,
-                        item,
-                    );
-                }
-                removed_any |= remove;
-                !remove
-            });
-            if removed_any {
-                self.obj.set_property(
-                    &KDE_WATCHER_INTERFACE,
-                    &REGISTERED_STATUS_NOTIFIER_ITEMS,
-                    kde.items.iter().cloned().collect::<Vec<_>>(),
-                )
+            for item in &removed {
+                self.dbus
+                    .send_signal(int, &WATCHER_PATH, &STATUS_NOTIFIER_ITEM_UNREGISTERED, item);
             }
+            self.obj
+                .set_property(int, &REGISTERED_STATUS_NOTIFIER_ITEMS, items);
         }
         for fdo in [true, false] {
             let int = match fdo {
@@ -179,6 +196,42 @@ impl Data {
             }
         }
     }
+
+    fn handle_name_acquired(self: &Arc<Self>, name: &str) {
+        let Some(fdo) = watcher_protocol(name) else {
+            return;
+        };
+        log::info!("Acting as {name}");
+        self.data(fdo).lock().active = true;
+        tokio::spawn(self.clone().recover_items(fdo));
+    }
+
+    fn handle_name_lost(&self, name: &str) {
+        let Some(fdo) = watcher_protocol(name) else {
+            return;
+        };
+        log::info!("No longer acting as {name}");
+        let int = match fdo {
+            true => &FDO_WATCHER_INTERFACE,
+            false => &KDE_WATCHER_INTERFACE,
+        };
+        *self.data(fdo).lock() = DataMut::default();
+        self.obj
+            .set_property(int, &REGISTERED_STATUS_NOTIFIER_ITEMS, Vec::<String>::new());
+        self.obj
+            .set_property(int, &IS_STATUS_NOTIFIER_HOST_REGISTERED, false);
+    }
+}
+
+/// Returns whether `name` is the FDO watcher name or `None` if it is not a watcher name.
+fn watcher_protocol(name: &str) -> Option<bool> {
+    if name == FDO_WATCHER_NAME.as_str() {
+        Some(true)
+    } else if name == KDE_WATCHER_NAME.as_str() {
+        Some(false)
+    } else {
+        None
+    }
 }
 
 pub fn create_watcher(dbus: &Arc<Connection>) {
@@ -194,8 +247,12 @@ pub fn create_watcher(dbus: &Arc<Connection>) {
         w.handle_name_owner_changed(name, old_owner, new_owner);
     })
     .detach();
-    dbus.request_name(FDO_WATCHER_NAME);
-    dbus.request_name(KDE_WATCHER_NAME);
+    let w = watcher.clone();
+    dbus.on_name_acquired(move |name| w.handle_name_acquired(&name))
+        .detach();
+    let w = watcher.clone();
+    dbus.on_name_lost(move |name| w.handle_name_lost(&name))
+        .detach();
     for fdo in [true, false] {
         let interface = match fdo {
             true => &FDO_WATCHER_INTERFACE,
@@ -242,4 +299,18 @@ pub fn create_watcher(dbus: &Arc<Connection>) {
             .obj
             .add_signal(interface, STATUS_NOTIFIER_HOST_REGISTERED, "");
     }
+    // If another watcher owns the name, the name is queued and acquired once the other
+    // watcher exits. Until then, this process only acts as a host.
+    for name in [FDO_WATCHER_NAME, KDE_WATCHER_NAME] {
+        let reply = dbus.request_name(name.clone(), BitFlags::default());
+        tokio::spawn(async move {
+            match reply.await {
+                Ok(RequestNameReply::InQueue) => {
+                    log::info!("Another process owns {name}. Waiting for it to exit");
+                }
+                Ok(_) => {}
+                Err(e) => log::error!("Could not request {name}: {}", Report::new(e)),
+            }
+        });
+    }
 }
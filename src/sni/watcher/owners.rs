@@ -0,0 +1,177 @@
+use ahash::{AHashMap, AHashSet};
+
+/// The items registered with a watcher and the unique names of the connections that
+/// own them.
+#[derive(Default)]
+pub struct Owners {
+    /// The owner of each item or `None` while the owner is being resolved.
+    items: AHashMap<String, Option<String>>,
+    /// The items of each owner.
+    owned: AHashMap<String, AHashSet<String>>,
+}
+
+impl Owners {
+    /// Adds an item whose owner is not yet known.
+    ///
+    /// Returns `false` if the item is already known.
+    pub fn insert(&mut self, item: &str) -> bool {
+        if self.items.contains_key(item) {
+            return false;
+        }
+        self.items.insert(item.to_string(), None);
+        true
+    }
+
+    /// Sets the owner of an item added with [Self::insert].
+    ///
+    /// Returns `false` if the item has been removed in the meantime.
+    pub fn resolve(&mut self, item: &str, owner: &str) -> bool {
+        let Some(o @ None) = self.items.get_mut(item) else {
+            return false;
+        };
+        *o = Some(owner.to_string());
+        self.owned
+            .entry(owner.to_string())
+            .or_default()
+            .insert(item.to_string());
+        true
+    }
+
+    /// Removes an item whose owner could not be resolved.
+    pub fn remove_unresolved(&mut self, item: &str) {
+        if let Some(None) = self.items.get(item) {
+            self.items.remove(item);
+        }
+    }
+
+    /// Removes the items that are owned by the connection `name` or that were
+    /// registered under the bus name `name`.
+    ///
+    /// Returns the removed items whose owner had been resolved.
+    pub fn remove_name(&mut self, name: &str) -> Vec<String> {
+        let mut removed = vec![];
+        if let Some(items) = self.owned.remove(name) {
+            for item in items {
+                self.items.remove(&item);
+                removed.push(item);
+            }
+        }
+        let registered: Vec<_> = self
+            .items
+            .keys()
+            .filter(|item| bus_name(item) == name)
+            .cloned()
+            .collect();
+        for item in registered {
+            let Some(Some(owner)) = self.items.remove(&item) else {
+                continue;
+            };
+            if let Some(owned) = self.owned.get_mut(&owner) {
+                owned.remove(&item);
+                if owned.is_empty() {
+                    self.owned.remove(&owner);
+                }
+            }
+            removed.push(item);
+        }
+        removed
+    }
+
+    /// Returns the items whose owner has been resolved.
+    pub fn resolved(&self) -> Vec<String> {
+        self.items
+            .iter()
+            .filter(|(_, owner)| owner.is_some())
+            .map(|(item, _)| item.clone())
+            .collect()
+    }
+}
+
+/// Returns the bus name that an item was registered under.
+pub fn bus_name(item: &str) -> &str {
+    match item.find('/') {
+        Some(p) => &item[..p],
+        None => item,
+    }
+}
+
+#[test]
+fn unique_name_prefixes_are_distinct() {
+    let mut owners = Owners::default();
+    for name in [":1.1", ":1.10", ":1.100"] {
+        let item = format!("{name}/StatusNotifierItem");
+        assert!(owners.insert(&item));
+        assert!(owners.resolve(&item, name));
+    }
+    assert_eq!(owners.remove_name(":1.1"), [":1.1/StatusNotifierItem"]);
+    let mut remaining = owners.resolved();
+    remaining.sort();
+    assert_eq!(
+        remaining,
+        [":1.10/StatusNotifierItem", ":1.100/StatusNotifierItem"],
+    );
+}
+
+#[test]
+fn well_known_names_are_removed_with_their_owner() {
+    let mut owners = Owners::default();
+    let item = "org.kde.StatusNotifierItem-5-1/StatusNotifierItem";
+    assert!(owners.insert(item));
+    assert!(owners.resolve(item, ":1.5"));
+    assert!(owners.remove_name(":1.50").is_empty());
+    assert_eq!(owners.remove_name(":1.5"), [item]);
+    assert!(owners.resolved().is_empty());
+}
+
+#[test]
+fn pending_items_are_removed_with_their_bus_name() {
+    let mut owners = Owners::default();
+    let item = "org.kde.StatusNotifierItem-5-1/StatusNotifierItem";
+    assert!(owners.insert(item));
+    assert!(!owners.insert(item));
+    assert!(owners
+        .remove_name("org.kde.StatusNotifierItem-5-1")
+        .is_empty());
+    assert!(!owners.resolve(item, ":1.5"));
+    assert!(owners.resolved().is_empty());
+}
+
+#[test]
+fn many_connections() {
+    let mut owners = Owners::default();
+    let connections = 1000;
+    for i in 0..connections {
+        let unique = format!(":1.{i}");
+        let items = [
+            format!("{unique}/org/ayatana/NotificationItem/app{i}"),
+            format!("org.kde.StatusNotifierItem-{i}-1/StatusNotifierItem"),
+            format!("org.freedesktop.StatusNotifierItem-{i}-2"),
+        ];
+        for item in &items {
+            assert!(owners.insert(item));
+            assert!(owners.resolve(item, &unique));
+        }
+    }
+    for i in (0..connections).step_by(2) {
+        assert_eq!(owners.remove_name(&format!(":1.{i}")).len(), 3);
+    }
+    for i in (1..connections).step_by(4) {
+        let name = format!("org.kde.StatusNotifierItem-{i}-1");
+        assert_eq!(owners.remove_name(&name).len(), 1);
+    }
+    let resolved: AHashSet<_> = owners.resolved().into_iter().collect();
+    assert_eq!(resolved.len(), 3 * connections / 2 - connections / 4);
+    for i in 0..connections {
+        let unique = format!(":1.{i}");
+        let item = format!("{unique}/org/ayatana/NotificationItem/app{i}");
+        assert_eq!(resolved.contains(&item), i % 2 == 1);
+        let item = format!("org.kde.StatusNotifierItem-{i}-1/StatusNotifierItem");
+        assert_eq!(resolved.contains(&item), i % 4 == 3);
+    }
+    for i in (1..connections).step_by(2) {
+        let unique = format!(":1.{i}");
+        let expected = if i % 4 == 1 { 2 } else { 3 };
+        assert_eq!(owners.remove_name(&unique).len(), expected);
+    }
+    assert!(owners.resolved().is_empty());
+}
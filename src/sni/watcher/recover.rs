@@ -0,0 +1,169 @@
+use {
+    crate::sni::{watcher::Data, DBUS_INTERFACE, DBUS_NAME, DBUS_PATH, GET_NAME_OWNER},
+    error_reporter::Report,
+    parking_lot::Mutex,
+    regex::Regex,
+    std::{
+        collections::HashSet,
+        sync::{Arc, LazyLock},
+        time::Duration,
+    },
+    zbus::{
+        names::{BusName, InterfaceName, MemberName},
+        zvariant::ObjectPath,
+    },
+};
+
+const LIST_NAMES: MemberName<'static> = MemberName::from_static_str_unchecked("ListNames");
+const INTROSPECTABLE: InterfaceName<'static> =
+    InterfaceName::from_static_str_unchecked("org.freedesktop.DBus.Introspectable");
+const INTROSPECT: MemberName<'static> = MemberName::from_static_str_unchecked("Introspect");
+
+const KDE_ITEM_NAME_PREFIX: &str = "org.kde.StatusNotifierItem-";
+const FDO_ITEM_NAME_PREFIX: &str = "org.freedesktop.StatusNotifierItem-";
+
+/// The maximum depth of the object tree that is searched for items.
+const MAX_DEPTH: usize = 6;
+/// The maximum number of objects that are introspected per connection.
+const MAX_OBJECTS: usize = 256;
+/// The maximum number of connections that are introspected at the same time.
+const MAX_CONNECTIONS: usize = 8;
+/// The timeout of the calls made during recovery.
+const CALL_TIMEOUT: Duration = Duration::from_secs(2);
+
+static INTERFACE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"<interface\s+name="org\.(kde|freedesktop)\.StatusNotifierItem""#).unwrap()
+});
+static CHILD_NODE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"<node\s+name="([^"/]+)""#).unwrap());
+
+impl Data {
+    /// Adds the items of the protocol that exist on the bus.
+    ///
+    /// Most applications only register their items when the watcher name appears. If
+    /// the bridge is restarted, these items would otherwise never be shown again.
+    ///
+    /// Names of the form `org.kde.StatusNotifierItem-*` and
+    /// `org.freedesktop.StatusNotifierItem-*` are items by convention. The objects of all
+    /// other connections are introspected to find items that registered with an object
+    /// path.
+    pub(super) async fn recover_items(self: Arc<Self>, fdo: bool) {
+        let names: Vec<String> = match self
+            .dbus
+            .call(DBUS_NAME, DBUS_INTERFACE, DBUS_PATH, LIST_NAMES, &())
+            .with_timeout(Some(CALL_TIMEOUT))
+            .await
+        {
+            Ok(n) => n,
+            Err(e) => {
+                log::error!("Could not list the names on the bus: {}", Report::new(e));
+                return;
+            }
+        };
+        let well_known: Vec<_> = names
+            .iter()
+            .filter_map(|name| {
+                let prefix = match fdo {
+                    true => FDO_ITEM_NAME_PREFIX,
+                    false => KDE_ITEM_NAME_PREFIX,
+                };
+                if !name.starts_with(prefix) {
+                    return None;
+                }
+                let owner = self
+                    .dbus
+                    .call::<String>(
+                        DBUS_NAME,
+                        DBUS_INTERFACE,
+                        DBUS_PATH,
+                        GET_NAME_OWNER,
+                        &name.as_str(),
+                    )
+                    .with_timeout(Some(CALL_TIMEOUT));
+                Some((name, owner))
+            })
+            .collect();
+        // The owners of these names are not introspected since they would otherwise be
+        // added a second time under their unique name.
+        let mut owners = HashSet::new();
+        for (name, owner) in well_known {
+            let Ok(owner) = owner.await else {
+                continue;
+            };
+            owners.insert(owner);
+            log::info!("Recovered item {name}");
+            match fdo {
+                true => self.add_item(fdo, name),
+                false => self.add_item(fdo, &format!("{name}/StatusNotifierItem")),
+            }
+        }
+        let pending: Vec<_> = names
+            .into_iter()
+            .filter(|name| name.starts_with(':') && !owners.contains(name))
+            .collect();
+        let pending = Arc::new(Mutex::new(pending));
+        for _ in 0..MAX_CONNECTIONS {
+            let slf = self.clone();
+            let pending = pending.clone();
+            tokio::spawn(async move {
+                loop {
+                    let Some(name) = pending.lock().pop() else {
+                        break;
+                    };
+                    slf.find_items(fdo, &name).await;
+                }
+            });
+        }
+    }
+
+    /// Searches the objects of the connection `name` for items of the protocol.
+    async fn find_items(self: &Arc<Self>, fdo: bool, name: &str) {
+        let Ok(destination) = BusName::try_from(name) else {
+            return;
+        };
+        let mut paths = vec!["/".to_string()];
+        let mut introspected = 0;
+        for depth in 0..MAX_DEPTH {
+            let remaining = MAX_OBJECTS - introspected;
+            paths.truncate(remaining);
+            introspected += paths.len();
+            let calls: Vec<_> = paths
+                .drain(..)
+                .filter_map(|path| {
+                    let object_path = ObjectPath::try_from(path.as_str()).ok()?;
+                    let call = self
+                        .dbus
+                        .call::<String>(&destination, INTROSPECTABLE, object_path, INTROSPECT, &())
+                        .with_timeout(Some(CALL_TIMEOUT));
+                    Some((path, call))
+                })
+                .collect();
+            for (path, call) in calls {
+                let Ok(xml) = call.await else {
+                    continue;
+                };
+                if let Some(c) = INTERFACE.captures(&xml) {
+                    if (&c[1] == "freedesktop") == fdo {
+                        let item = format!("{name}{path}");
+                        log::info!("Recovered item {item}");
+                        self.add_item(fdo, &item);
+                    }
+                    continue;
+                }
+                if depth + 1 == MAX_DEPTH {
+                    continue;
+                }
+                for c in CHILD_NODE.captures_iter(&xml) {
+                    let child = match path.as_str() {
+                        "/" => format!("/{}", &c[1]),
+                        _ => format!("{path}/{}", &c[1]),
+                    };
+                    paths.push(child);
+                }
+            }
+            if paths.is_empty() {
+                break;
+            }
+        }
+    }
+}
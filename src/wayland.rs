@@ -1,3 +1,5 @@
+mod control;
+mod desktop;
 mod item;
 mod scale;
 mod seat;
@@ -5,24 +7,34 @@ mod sni_proxy;
 mod tray;
 mod utils;
 
+pub use control::{
+    ItemInfo, ACTIVATE_ITEM, CONTROL_INTERFACE, CONTROL_NAME, CONTROL_PATH, LIST_ITEMS, OPEN_MENU,
+    RELOAD_CONFIG,
+};
 use {
     crate::{
-        sni::{MutableProperty, SniItem, SniMenuDelta},
+        settings::{self, LoadError},
+        sni::{self, MutableProperty, SniItem, SniItemId, SniMenuDelta},
         wayland::{
-            item::{Item, Items},
+            control::Control,
+            item::{Item, Items, MovieSource},
             scale::{Logical, Scale},
             seat::Seat,
             sni_proxy::{event_stream, EventSink},
             tray::{
-                item::menu::MenuId,
-                protocols::ext_tray_v1::client::{
-                    ext_tray_item_v1, ext_tray_item_v1::ExtTrayItemV1, ext_tray_v1::ExtTrayV1,
+                item::{icon, menu::MenuId, movie::Movie},
+                protocols::{
+                    ext_tray_v1::client::{
+                        ext_tray_item_v1, ext_tray_item_v1::ExtTrayItemV1, ext_tray_v1::ExtTrayV1,
+                    },
+                    wlr_layer_shell::LayerTray,
                 },
                 PopupId, TrayItemId, TraySurfaceId, Trays,
             },
         },
     },
     ahash::AHashMap,
+    error_reporter::Report,
     std::{
         convert::Infallible,
         future::poll_fn,
@@ -40,11 +52,15 @@ use {
             wl_buffer,
             wl_callback::{self, WlCallback},
             wl_compositor,
+            wl_keyboard::{self, KeyState, WlKeyboard},
+            wl_output::WlOutput,
             wl_pointer::{self, ButtonState, WlPointer},
             wl_registry,
             wl_seat::{self, WlSeat},
             wl_shm::WlShm,
             wl_shm_pool::WlShmPool,
+            wl_subcompositor::WlSubcompositor,
+            wl_subsurface::WlSubsurface,
             wl_surface,
         },
         ConnectError, Connection, Dispatch, DispatchError, QueueHandle,
@@ -62,13 +78,23 @@ use {
             single_pixel_buffer::v1::client::wp_single_pixel_buffer_manager_v1::WpSinglePixelBufferManagerV1,
             viewporter::client::{wp_viewport::WpViewport, wp_viewporter},
         },
-        xdg::shell::client::{
-            xdg_popup::{self, XdgPopup},
-            xdg_positioner::XdgPositioner,
-            xdg_surface::{self, XdgSurface},
-            xdg_wm_base::XdgWmBase,
+        xdg::{
+            activation::v1::client::{
+                xdg_activation_token_v1::{self, XdgActivationTokenV1},
+                xdg_activation_v1::XdgActivationV1,
+            },
+            shell::client::{
+                xdg_popup::{self, XdgPopup},
+                xdg_positioner::XdgPositioner,
+                xdg_surface::{self, XdgSurface},
+                xdg_wm_base::XdgWmBase,
+            },
         },
     },
+    wayland_protocols_wlr::layer_shell::v1::client::{
+        zwlr_layer_shell_v1::ZwlrLayerShellV1,
+        zwlr_layer_surface_v1::{self, ZwlrLayerSurfaceV1},
+    },
     wl_buffer::WlBuffer,
     wl_compositor::WlCompositor,
     wl_surface::WlSurface,
@@ -93,7 +119,7 @@ pub enum WaylandError {
     WaylandRecv(#[source] wayland_backend::client::WaylandError),
 }
 
-pub async fn run() -> Result<Infallible, WaylandError> {
+pub async fn run(watcher: bool) -> Result<Infallible, WaylandError> {
     let conn = Connection::connect_to_env()?;
 
     let mut event_queue = conn.new_event_queue::<State>();
@@ -104,10 +130,7 @@ pub async fn run() -> Result<Infallible, WaylandError> {
 
     display.sync(&qhandle, InitialRoundtrip);
 
-    let dbus = zbus::Connection::session()
-        .await
-        .map_err(WaylandError::ConnectDbus)?;
-    let dbus = bussy::Connection::wrap(&dbus);
+    let dbus = sni::connect().await.map_err(WaylandError::ConnectDbus)?;
     let (sink, mut stream) = event_stream();
 
     let mut state = State {
@@ -116,8 +139,11 @@ pub async fn run() -> Result<Infallible, WaylandError> {
         items: Default::default(),
         trays: Default::default(),
         seats: Default::default(),
+        outputs: Default::default(),
+        control: None,
         sink: sink.clone(),
         dbus: dbus.connection.clone(),
+        watcher,
     };
 
     let afd = AsyncFd::new(conn.as_fd()).map_err(WaylandError::AsyncFd)?;
@@ -161,6 +187,9 @@ struct SingletonsOpt {
     wp_cursor_shape_manager_v1: Option<WpCursorShapeManagerV1>,
     xdg_wm_base: Option<XdgWmBase>,
     xdg_wm_base_version: u32,
+    xdg_activation_v1: Option<XdgActivationV1>,
+    wl_subcompositor: Option<WlSubcompositor>,
+    zwlr_layer_shell_v1: Option<ZwlrLayerShellV1>,
 }
 
 struct Singletons {
@@ -173,6 +202,9 @@ struct Singletons {
     xdg_wm_base: XdgWmBase,
     xdg_wm_base_version: u32,
     wp_fractional_scale_manager_v1: Option<WpFractionalScaleManagerV1>,
+    xdg_activation_v1: Option<XdgActivationV1>,
+    wl_subcompositor: Option<WlSubcompositor>,
+    zwlr_layer_shell_v1: Option<ZwlrLayerShellV1>,
 }
 
 struct State {
@@ -181,8 +213,12 @@ struct State {
     items: Items,
     trays: Trays,
     seats: AHashMap<u32, Seat>,
+    outputs: AHashMap<u32, WlOutput>,
+    control: Option<Control>,
     sink: EventSink,
     dbus: Arc<bussy::Connection>,
+    /// Whether to act as the StatusNotifierWatcher if no other watcher is running.
+    watcher: bool,
 }
 
 fn s(s: &Option<Singletons>) -> &Singletons {
@@ -198,16 +234,135 @@ impl State {
     fn handle_new_sni_item(&mut self, sni: Arc<SniItem>) {
         let mut item = Item {
             sni: sni.clone(),
+            sink: self.sink.clone(),
             props: sni.properties(),
             icon: Default::default(),
             attention_icon: Default::default(),
+            overlay_icon: Default::default(),
+            attention_movie: None,
+            attention_movie_source: None,
+            tooltip_icon: Default::default(),
             menu: Default::default(),
+            overrides: Default::default(),
+            desktop_entry: None,
+            hidden: false,
         };
         if let Some(s) = &self.singletons {
             item.initialize();
             self.trays.add_item(s, &item);
         }
+        if let Some(control) = &self.control {
+            control.item_added(&item);
+        }
         self.items.items.insert(sni.id(), item);
+        if let Some(s) = &self.singletons {
+            self.trays.arrange(&self.items, s);
+        }
+    }
+
+    fn set_item_hidden(&mut self, sni: &Arc<SniItem>, hidden: bool) {
+        let Some(item) = self.items.items.get_mut(&sni.id()) else {
+            return;
+        };
+        if item.hidden == hidden {
+            return;
+        }
+        item.hidden = hidden;
+        let s = s(&self.singletons);
+        self.trays.handle_item_removed(sni.id());
+        self.trays.add_item(s, item);
+        self.trays.arrange(&self.items, s);
+    }
+
+    /// Creates a layer-shell panel on the output if the compositor has no tray.
+    fn create_panel(&mut self, output_name: u32) {
+        let Some(s) = &self.singletons else {
+            return;
+        };
+        if self.trays.has_ext_tray() {
+            return;
+        }
+        let (Some(layer_shell), Some(subcompositor)) =
+            (&s.zwlr_layer_shell_v1, &s.wl_subcompositor)
+        else {
+            return;
+        };
+        let Some(output) = self.outputs.get(&output_name) else {
+            return;
+        };
+        let panel = LayerTray::new(s, layer_shell, subcompositor, output, output_name);
+        let tray = self.trays.create_tray(Box::new(panel), output_name);
+        for item in self.items.items.values() {
+            tray.add_item(s, item);
+        }
+    }
+
+    /// Reloads the config file and re-renders everything with the new settings.
+    fn reload_settings(&mut self) -> Result<(), LoadError> {
+        let old = settings::reload()?;
+        let new = settings::get();
+        icon::clear_caches();
+        self.rebuild_desktop_index();
+        if (old.scale, &old.panel) != (new.scale, &new.panel) && !self.trays.has_ext_tray() {
+            self.trays.remove_panels();
+            let outputs: Vec<_> = self.outputs.keys().copied().collect();
+            for output in outputs {
+                self.create_panel(output);
+            }
+        }
+        let s = s(&self.singletons);
+        for item in self.items.items.values_mut() {
+            let old_overrides = item.overrides.clone();
+            if item.handle_settings_changed(old.theme != new.theme) {
+                let new = &item.overrides;
+                if (new.hide, new.priority) != (old_overrides.hide, old_overrides.priority) {
+                    self.trays.handle_item_removed(item.sni.id());
+                    self.trays.add_item(s, item);
+                }
+            }
+        }
+        self.trays.handle_settings_changed(&self.items, s);
+        self.trays.arrange(&self.items, s);
+        log::info!("Reloaded the config");
+        Ok(())
+    }
+
+    /// Re-reads the desktop entries in the background and updates the items afterwards.
+    fn rebuild_desktop_index(&self) {
+        let sink = self.sink.clone();
+        desktop::rebuild(move || sink.send(State::handle_desktop_index_rebuilt));
+    }
+
+    fn handle_movie_loaded(
+        &mut self,
+        id: SniItemId,
+        source: MovieSource,
+        movie: Option<Arc<Movie>>,
+    ) {
+        let Some(item) = self.items.items.get_mut(&id) else {
+            return;
+        };
+        if item.attention_movie_source.as_ref() != Some(&source) {
+            return;
+        }
+        item.attention_movie = movie;
+        if let Some(s) = &self.singletons {
+            self.trays
+                .handle_item_prop_changed(s, item, MutableProperty::AttentionIcon);
+        }
+    }
+
+    fn handle_desktop_index_rebuilt(&mut self) {
+        let Some(s) = &self.singletons else {
+            return;
+        };
+        for item in self.items.items.values_mut() {
+            if item.update_desktop_entry() {
+                item.update_icon();
+                self.trays
+                    .handle_item_prop_changed(s, item, MutableProperty::Title);
+            }
+        }
     }
 
     fn handle_sni_item_prop_changed(&mut self, sni: &Arc<SniItem>, prop: MutableProperty) {
@@ -218,15 +373,47 @@ impl State {
         match prop {
             MutableProperty::Icon => item.update_icon(),
             MutableProperty::AttentionIcon => item.update_attention_icon(),
+            MutableProperty::OverlayIcon => item.update_overlay_icon(),
+            MutableProperty::ToolTip => item.update_tooltip(),
+            MutableProperty::Title => item.update_icon(),
+            MutableProperty::Id => {
+                item.update_desktop_entry();
+                item.update_icon();
+            }
+            MutableProperty::IconThemePath => {
+                item.update_icon();
+                item.update_attention_icon();
+                item.update_overlay_icon();
+                item.update_tooltip();
+            }
             _ => {}
         }
-        self.trays
-            .handle_item_prop_changed(s(&self.singletons), item, prop);
+        let s = s(&self.singletons);
+        let old_overrides = item.overrides.clone();
+        if item.update_overrides() {
+            item.update_icon();
+            let new = &item.overrides;
+            if (new.hide, new.priority) != (old_overrides.hide, old_overrides.priority) {
+                self.trays.handle_item_removed(sni.id());
+                self.trays.add_item(s, item);
+                self.trays.arrange(&self.items, s);
+                return;
+            }
+            self.trays
+                .handle_item_prop_changed(s, item, MutableProperty::Icon);
+        }
+        self.trays.handle_item_prop_changed(s, item, prop);
     }
 
     fn handle_sni_item_removed(&mut self, item: &Arc<SniItem>) {
-        self.items.items.remove(&item.id());
+        let removed = self.items.items.remove(&item.id());
+        if let (Some(control), Some(removed)) = (&self.control, &removed) {
+            control.item_removed(removed);
+        }
         self.trays.handle_item_removed(item.id());
+        if let Some(s) = &self.singletons {
+            self.trays.arrange(&self.items, s);
+        }
     }
 
     fn handle_sni_menu_changed(&mut self, item: &Arc<SniItem>, delta: SniMenuDelta) {
@@ -238,6 +425,11 @@ impl State {
             .handle_menu_changed(s(&self.singletons), item, &delta);
     }
 
+    fn handle_animation_tick(&mut self, id: TrayItemId) {
+        self.trays
+            .handle_animation_tick(&self.items, s(&self.singletons), id);
+    }
+
     fn handle_seat_timeout(&mut self, seat_name: u32, timeout_id: usize) {
         let Some(seat) = self.seats.get_mut(&seat_name) else {
             return;
@@ -305,6 +497,10 @@ impl Dispatch<wl_registry::WlRegistry, ()> for State {
                         Some(registry.bind::<XdgWmBase, _, _>(name, version, qh, ()));
                     state.singletons_opt.xdg_wm_base_version = version;
                 }
+                "xdg_activation_v1" => {
+                    state.singletons_opt.xdg_activation_v1 =
+                        Some(registry.bind::<XdgActivationV1, _, _>(name, 1, qh, ()));
+                }
                 "wp_cursor_shape_manager_v1" => {
                     state.singletons_opt.wp_cursor_shape_manager_v1 =
                         Some(registry.bind::<WpCursorShapeManagerV1, _, _>(name, 1, qh, ()));
@@ -318,6 +514,19 @@ impl Dispatch<wl_registry::WlRegistry, ()> for State {
                         }
                     }
                 }
+                "wl_subcompositor" => {
+                    state.singletons_opt.wl_subcompositor =
+                        Some(registry.bind::<WlSubcompositor, _, _>(name, 1, qh, ()));
+                }
+                "zwlr_layer_shell_v1" => {
+                    state.singletons_opt.zwlr_layer_shell_v1 =
+                        Some(registry.bind::<ZwlrLayerShellV1, _, _>(name, version.min(3), qh, ()));
+                }
+                "wl_output" => {
+                    let output = registry.bind::<WlOutput, _, _>(name, 1, qh, ());
+                    state.outputs.insert(name, output);
+                    state.create_panel(name);
+                }
                 "wl_seat" => {
                     let seat = registry.bind::<WlSeat, _, _>(name, version.min(8), qh, name);
                     state.seats.insert(name, Seat::new(seat, name));
@@ -325,6 +534,7 @@ impl Dispatch<wl_registry::WlRegistry, ()> for State {
                 _ => {}
             },
             Event::GlobalRemove { name } => {
+                state.outputs.remove(&name);
                 if let Some(mut seat) = state.seats.remove(&name) {
                     seat.handle_remove(&mut state.trays);
                 }
@@ -370,13 +580,56 @@ impl Dispatch<WlCallback, InitialRoundtrip> for State {
                 .singletons_opt
                 .wp_fractional_scale_manager_v1
                 .take(),
+            xdg_activation_v1: state.singletons_opt.xdg_activation_v1.take(),
+            wl_subcompositor: state.singletons_opt.wl_subcompositor.take(),
+            zwlr_layer_shell_v1: state.singletons_opt.zwlr_layer_shell_v1.take(),
         };
         for item in state.items.items.values_mut() {
             item.initialize();
             state.trays.add_item(&singletons, item);
         }
         state.singletons = Some(singletons);
-        sni_proxy::spawn(&state.dbus, &state.sink);
+        if !state.trays.has_ext_tray() {
+            if s(&state.singletons).zwlr_layer_shell_v1.is_none() {
+                log::warn!("Compositor supports neither ext_tray_v1 nor zwlr_layer_shell_v1");
+            }
+            let outputs: Vec<_> = state.outputs.keys().copied().collect();
+            for output in outputs {
+                state.create_panel(output);
+            }
+        }
+        sni_proxy::spawn(&state.dbus, state.watcher, &state.sink);
+        state.control = Some(Control::new(&state.dbus, &state.sink));
+        state.rebuild_desktop_index();
+        let sink = state.sink.clone();
+        settings::watch(move || {
+            sink.send(|state| {
+                if let Err(e) = state.reload_settings() {
+                    log::error!("Could not reload the config: {}", Report::new(e));
+                }
+            });
+        });
+    }
+}
+
+impl Dispatch<WlCallback, TrayItemId> for State {
+    fn event(
+        state: &mut Self,
+        proxy: &WlCallback,
+        event: wl_callback::Event,
+        id: &TrayItemId,
+        _conn: &Connection,
+        _qhandle: &QueueHandle<Self>,
+    ) {
+        use wl_callback::Event;
+        match event {
+            Event::Done { .. } => {
+                state
+                    .trays
+                    .handle_frame_done(s(&state.singletons), *id, proxy);
+            }
+            _ => {}
+        }
     }
 }
 
@@ -512,7 +765,61 @@ impl Dispatch<WlPointer, u32> for State {
                 let WEnum::Value(axis) = axis else {
                     return;
                 };
-                seat.handle_axis_value120(&mut state.trays, axis, value120);
+                seat.handle_axis_value120(
+                    s(&state.singletons),
+                    &mut state.trays,
+                    &state.items,
+                    axis,
+                    value120,
+                );
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<WlKeyboard, u32> for State {
+    fn event(
+        state: &mut Self,
+        _proxy: &WlKeyboard,
+        event: wl_keyboard::Event,
+        &name: &u32,
+        _conn: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let Some(seat) = state.seats.get_mut(&name) else {
+            return;
+        };
+        use wl_keyboard::Event;
+        match event {
+            Event::Keymap {
+                format: WEnum::Value(format),
+                fd,
+                size,
+            } => {
+                seat.handle_keymap(format, fd, size);
+            }
+            Event::Enter { surface, .. } => {
+                seat.handle_keyboard_enter(&state.trays, &surface);
+            }
+            Event::Leave { .. } => {
+                seat.handle_keyboard_leave();
+            }
+            Event::Key {
+                key,
+                state: WEnum::Value(KeyState::Pressed),
+                ..
+            } => {
+                seat.handle_key_pressed(&state.items, s(&state.singletons), &mut state.trays, key);
+            }
+            Event::Modifiers {
+                mods_depressed,
+                mods_latched,
+                mods_locked,
+                group,
+                ..
+            } => {
+                seat.handle_modifiers(mods_depressed, mods_latched, mods_locked, group);
             }
             _ => {}
         }
@@ -555,6 +862,65 @@ impl Dispatch<XdgPopup, PopupId> for State {
             Event::Repositioned { token } => {
                 state.trays.handle_popup_repositioned(*id, token);
             }
+            Event::Configure { height, .. } => {
+                state
+                    .trays
+                    .handle_popup_geometry(&state.items, s(&state.singletons), *id, height);
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<ZwlrLayerSurfaceV1, u32> for State {
+    fn event(
+        state: &mut Self,
+        _proxy: &ZwlrLayerSurfaceV1,
+        event: zwlr_layer_surface_v1::Event,
+        &name: &u32,
+        _conn: &Connection,
+        _qhandle: &QueueHandle<Self>,
+    ) {
+        use zwlr_layer_surface_v1::Event;
+        match event {
+            Event::Configure {
+                serial,
+                width,
+                height,
+            } => {
+                state.trays.handle_panel_configure(
+                    &state.items,
+                    s(&state.singletons),
+                    name,
+                    serial,
+                    width,
+                    height,
+                );
+            }
+            Event::Closed => {
+                state.trays.handle_global_remove(name);
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<XdgActivationTokenV1, TrayItemId> for State {
+    fn event(
+        state: &mut Self,
+        proxy: &XdgActivationTokenV1,
+        event: xdg_activation_token_v1::Event,
+        id: &TrayItemId,
+        _conn: &Connection,
+        _qhandle: &QueueHandle<Self>,
+    ) {
+        use xdg_activation_token_v1::Event;
+        match event {
+            Event::Done { token } => {
+                state
+                    .trays
+                    .handle_activation_token(s(&state.singletons), *id, proxy, &token);
+            }
             _ => {}
         }
     }
@@ -629,7 +995,10 @@ impl Dispatch<WlSurface, TrayItemId> for State {
 delegate_noop!(State: ignore ExtTrayV1);
 delegate_noop!(State: ignore WlCompositor);
 delegate_noop!(State: ignore WlShm);
+delegate_noop!(State: ignore WlOutput);
 delegate_noop!(State: ignore WlShmPool);
+delegate_noop!(State: ignore WlSubcompositor);
+delegate_noop!(State: ignore WlSubsurface);
 delegate_noop!(State: ignore WlSurface);
 delegate_noop!(State: ignore WpCursorShapeDeviceV1);
 delegate_noop!(State: ignore WpCursorShapeManagerV1);
@@ -637,5 +1006,7 @@ delegate_noop!(State: ignore WpFractionalScaleManagerV1);
 delegate_noop!(State: ignore WpSinglePixelBufferManagerV1);
 delegate_noop!(State: ignore WpViewport);
 delegate_noop!(State: ignore WpViewporter);
+delegate_noop!(State: ignore XdgActivationV1);
 delegate_noop!(State: ignore XdgPositioner);
+delegate_noop!(State: ignore ZwlrLayerShellV1);
 delegate_noop!(State: ignore XdgWmBase);
@@ -0,0 +1,240 @@
+use {
+    crate::wayland::{
+        item::{Item, Items},
+        sni_proxy::EventSink,
+        State,
+    },
+    bussy::{BitFlags, Connection, Object, PendingReply},
+    error_reporter::Report,
+    std::{fmt::Write, sync::Arc},
+    zbus::{
+        names::{InterfaceName, MemberName, WellKnownName},
+        zvariant::ObjectPath,
+    },
+};
+
+pub const CONTROL_NAME: WellKnownName<'static> =
+    WellKnownName::from_static_str_unchecked("org.wl_tray_bridge");
+pub static CONTROL_INTERFACE: InterfaceName<'static> =
+    InterfaceName::from_static_str_unchecked("org.wl_tray_bridge.Control");
+pub const CONTROL_PATH: ObjectPath<'static> =
+    ObjectPath::from_static_str_unchecked("/org/wl_tray_bridge/Control");
+
+pub const LIST_ITEMS: MemberName<'static> = MemberName::from_static_str_unchecked("ListItems");
+pub const ACTIVATE_ITEM: MemberName<'static> =
+    MemberName::from_static_str_unchecked("ActivateItem");
+pub const OPEN_MENU: MemberName<'static> = MemberName::from_static_str_unchecked("OpenMenu");
+const HIDE_ITEM: MemberName<'static> = MemberName::from_static_str_unchecked("HideItem");
+const SHOW_ITEM: MemberName<'static> = MemberName::from_static_str_unchecked("ShowItem");
+pub const RELOAD_CONFIG: MemberName<'static> =
+    MemberName::from_static_str_unchecked("ReloadConfig");
+const DUMP_STATE: MemberName<'static> = MemberName::from_static_str_unchecked("DumpState");
+
+const SIG_ITEM_ADDED: MemberName<'static> = MemberName::from_static_str_unchecked("ItemAdded");
+const SIG_ITEM_REMOVED: MemberName<'static> = MemberName::from_static_str_unchecked("ItemRemoved");
+
+/// The `org.wl_tray_bridge.Control` interface used to control the running bridge.
+pub struct Control {
+    dbus: Arc<Connection>,
+    _obj: Arc<Object>,
+}
+
+/// The description of an item returned by `ListItems`.
+pub type ItemInfo = (String, String, String, String, String);
+
+impl Control {
+    pub fn new(dbus: &Arc<Connection>, sink: &EventSink) -> Self {
+        let obj = dbus.add_obj(&CONTROL_PATH);
+        let s = sink.clone();
+        obj.add_method(
+            &CONTROL_INTERFACE,
+            LIST_ITEMS,
+            "",
+            "a(sssss)",
+            move |(), pr| {
+                s.send(move |state| state.control_list_items(pr));
+            },
+        );
+        let s = sink.clone();
+        obj.add_method(
+            &CONTROL_INTERFACE,
+            ACTIVATE_ITEM,
+            "s",
+            "",
+            move |id: String, pr| {
+                s.send(move |state| state.control_activate_item(&id, pr));
+            },
+        );
+        let s = sink.clone();
+        obj.add_method(
+            &CONTROL_INTERFACE,
+            OPEN_MENU,
+            "s",
+            "",
+            move |id: String, pr| {
+                s.send(move |state| state.control_open_menu(&id, pr));
+            },
+        );
+        let s = sink.clone();
+        obj.add_method(
+            &CONTROL_INTERFACE,
+            HIDE_ITEM,
+            "s",
+            "",
+            move |id: String, pr| {
+                s.send(move |state| state.control_set_hidden(&id, true, pr));
+            },
+        );
+        let s = sink.clone();
+        obj.add_method(
+            &CONTROL_INTERFACE,
+            SHOW_ITEM,
+            "s",
+            "",
+            move |id: String, pr| {
+                s.send(move |state| state.control_set_hidden(&id, false, pr));
+            },
+        );
+        let s = sink.clone();
+        obj.add_method(&CONTROL_INTERFACE, RELOAD_CONFIG, "", "", move |(), pr| {
+            s.send(move |state| state.control_reload_config(pr));
+        });
+        let s = sink.clone();
+        obj.add_method(&CONTROL_INTERFACE, DUMP_STATE, "", "s", move |(), pr| {
+            s.send(move |state| state.control_dump_state(pr));
+        });
+        obj.add_signal(&CONTROL_INTERFACE, SIG_ITEM_ADDED, "s");
+        obj.add_signal(&CONTROL_INTERFACE, SIG_ITEM_REMOVED, "s");
+        dbus.request_name(CONTROL_NAME, BitFlags::default());
+        Self {
+            dbus: dbus.clone(),
+            _obj: obj,
+        }
+    }
+
+    pub fn item_added(&self, item: &Item) {
+        self.dbus.send_signal(
+            &CONTROL_INTERFACE,
+            &CONTROL_PATH,
+            &SIG_ITEM_ADDED,
+            &item_id(item),
+        );
+    }
+
+    pub fn item_removed(&self, item: &Item) {
+        self.dbus.send_signal(
+            &CONTROL_INTERFACE,
+            &CONTROL_PATH,
+            &SIG_ITEM_REMOVED,
+            &item_id(item),
+        );
+    }
+}
+
+fn item_id(item: &Item) -> String {
+    item.props.id.as_deref().cloned().unwrap_or_default()
+}
+
+fn item_info(item: &Item) -> ItemInfo {
+    let prop = |p: &Option<Arc<String>>| p.as_deref().cloned().unwrap_or_default();
+    (
+        item_id(item),
+        prop(&item.props.title),
+        prop(&item.props.status),
+        item.sni.bus_name().to_string(),
+        prop(&item.props.category),
+    )
+}
+
+/// Finds an item by its SNI `Id` or, if no such item exists, by its bus name.
+fn find_item<'a>(items: &'a Items, id: &str) -> Option<&'a Item> {
+    let items = || items.items.values();
+    items()
+        .find(|i| i.props.id.as_deref().map(|s| &**s) == Some(id))
+        .or_else(|| items().find(|i| i.sni.bus_name() == id))
+}
+
+impl State {
+    fn control_list_items(&mut self, mut pr: PendingReply) {
+        let items: Vec<_> = self.items.items.values().map(item_info).collect();
+        pr.send(&items);
+    }
+
+    fn control_activate_item(&mut self, id: &str, mut pr: PendingReply) {
+        let Some(item) = find_item(&self.items, id) else {
+            pr.send_err(&format!("There is no item with id {id}"));
+            return;
+        };
+        item.sni
+            .activate(0, 0, move |ok| match ok {
+                true => pr.send(&()),
+                false => pr.send_err("The item could not be activated"),
+            })
+            .detach();
+    }
+
+    fn control_open_menu(&mut self, id: &str, mut pr: PendingReply) {
+        let Some(item) = find_item(&self.items, id) else {
+            pr.send_err(&format!("There is no item with id {id}"));
+            return;
+        };
+        let seat = self
+            .seats
+            .values()
+            .find_map(|seat| Some((seat, seat.last_serial()?)));
+        let Some((seat, serial)) = seat else {
+            pr.send_err("No seat has interacted with the bridge yet");
+            return;
+        };
+        let Some(s) = &self.singletons else {
+            pr.send_err("The bridge is not initialized yet");
+            return;
+        };
+        match self.trays.open_menu_with_serial(seat, serial, s, item) {
+            true => pr.send(&()),
+            false => pr.send_err("The item is not shown in any tray"),
+        }
+    }
+
+    fn control_set_hidden(&mut self, id: &str, hidden: bool, mut pr: PendingReply) {
+        let Some(sni) = find_item(&self.items, id).map(|i| i.sni.clone()) else {
+            pr.send_err(&format!("There is no item with id {id}"));
+            return;
+        };
+        self.set_item_hidden(&sni, hidden);
+        pr.send(&());
+    }
+
+    fn control_reload_config(&mut self, mut pr: PendingReply) {
+        match self.reload_settings() {
+            Ok(()) => pr.send(&()),
+            Err(e) => pr.send_err(&Report::new(e).to_string()),
+        }
+    }
+
+    fn control_dump_state(&mut self, mut pr: PendingReply) {
+        let mut res = String::new();
+        let _ = writeln!(res, "items:");
+        for item in self.items.items.values() {
+            let (id, title, status, bus_name, category) = item_info(item);
+            let _ = writeln!(
+                res,
+                "  {id:?}: title={title:?} status={status:?} bus-name={bus_name:?} \
+                 category={category:?} hidden={} overrides={:?}",
+                item.hidden, item.overrides,
+            );
+        }
+        let _ = writeln!(res, "trays:");
+        self.trays.dump(&mut res);
+        let _ = writeln!(res, "seats:");
+        for seat in self.seats.values() {
+            let _ = writeln!(
+                res,
+                "  {}: last-serial={:?}",
+                seat.name(),
+                seat.last_serial()
+            );
+        }
+        pr.send(&res);
+    }
+}
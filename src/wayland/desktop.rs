@@ -0,0 +1,228 @@
+use {
+    ahash::AHashMap,
+    error_reporter::Report,
+    ini::{Ini, ParseOption},
+    parking_lot::Mutex,
+    std::{
+        env::var,
+        path::{Path, PathBuf},
+        sync::{Arc, LazyLock},
+        thread,
+    },
+};
+
+/// The parts of a desktop entry that are used as fallbacks for items.
+#[derive(Debug, Eq, PartialEq)]
+pub struct DesktopEntry {
+    pub name: Option<Arc<String>>,
+    pub icon: Option<Arc<String>>,
+}
+
+/// The desktop entries of all applications indexed by lowercase keys.
+#[derive(Default)]
+struct Index {
+    ids: AHashMap<String, Arc<DesktopEntry>>,
+    /// The last components of reverse-DNS desktop file ids.
+    short_ids: AHashMap<String, Arc<DesktopEntry>>,
+    wm_classes: AHashMap<String, Arc<DesktopEntry>>,
+    /// The file names of the programs in `Exec`.
+    programs: AHashMap<String, Arc<DesktopEntry>>,
+}
+
+/// The index or `None` if it has not been built yet.
+static INDEX: Mutex<Option<Arc<Index>>> = Mutex::new(None);
+
+/// Builds the index in a background thread and calls `done` once it has been replaced.
+pub fn rebuild(done: impl FnOnce() + Send + 'static) {
+    thread::spawn(move || {
+        let mut index = Index::default();
+        for dir in &*APPLICATION_DIRS {
+            index.add_dir(dir, "");
+        }
+        *INDEX.lock() = Some(Arc::new(index));
+        done();
+    });
+}
+
+/// Finds the desktop entry of an item.
+///
+/// `keys` are tried in order, e.g. the `Id` of the item, its bus name, and the file
+/// name of its executable.
+pub fn find<'a>(keys: impl IntoIterator<Item = &'a str>) -> Option<Arc<DesktopEntry>> {
+    let index = INDEX.lock().clone()?;
+    index.find(keys)
+}
+
+impl Index {
+    fn find<'a>(&self, keys: impl IntoIterator<Item = &'a str>) -> Option<Arc<DesktopEntry>> {
+        for key in keys {
+            let key = key.to_lowercase();
+            for map in [&self.ids, &self.short_ids, &self.wm_classes, &self.programs] {
+                if let Some(entry) = map.get(&key) {
+                    return Some(entry.clone());
+                }
+            }
+        }
+        None
+    }
+
+    fn add_dir(&mut self, dir: &Path, prefix: &str) {
+        let Ok(entries) = dir.read_dir() else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+                continue;
+            };
+            if path.is_dir() {
+                self.add_dir(&path, &format!("{prefix}{name}-"));
+                continue;
+            }
+            let Some(id) = name.strip_suffix(".desktop") else {
+                continue;
+            };
+            let id = format!("{prefix}{id}").to_lowercase();
+            // Entries in earlier directories take precedence.
+            if self.ids.contains_key(&id) {
+                continue;
+            }
+            let Some((entry, wm_class, program)) = parse_entry(&path) else {
+                continue;
+            };
+            let entry = Arc::new(entry);
+            if let Some((_, last)) = id.rsplit_once('.') {
+                self.short_ids
+                    .entry(last.to_string())
+                    .or_insert_with(|| entry.clone());
+            }
+            self.ids.insert(id, entry.clone());
+            if let Some(wm_class) = wm_class {
+                self.wm_classes
+                    .entry(wm_class.to_lowercase())
+                    .or_insert_with(|| entry.clone());
+            }
+            if let Some(program) = program {
+                self.programs.entry(program.to_lowercase()).or_insert(entry);
+            }
+        }
+    }
+}
+
+/// Parses a desktop file. Returns the entry, its `StartupWMClass`, and the file name
+/// of the program it executes.
+fn parse_entry(path: &Path) -> Option<(DesktopEntry, Option<String>, Option<String>)> {
+    let contents = std::fs::read_to_string(path).ok()?;
+    let options = ParseOption {
+        enabled_quote: false,
+        enabled_escape: false,
+    };
+    let ini = match Ini::load_from_str_opt(&contents, options) {
+        Ok(ini) => ini,
+        Err(e) => {
+            log::debug!("Could not parse {}: {}", path.display(), Report::new(e));
+            return None;
+        }
+    };
+    let desc = ini.section(Some("Desktop Entry"))?;
+    if desc.get("Type") != Some("Application") || desc.get("Hidden") == Some("true") {
+        return None;
+    }
+    let string = |name: &str| {
+        desc.get(name)
+            .filter(|v| !v.is_empty())
+            .map(|v| Arc::new(v.to_string()))
+    };
+    let entry = DesktopEntry {
+        name: string("Name"),
+        icon: string("Icon"),
+    };
+    let wm_class = desc.get("StartupWMClass").map(ToOwned::to_owned);
+    let program = desc.get("Exec").and_then(program);
+    Some((entry, wm_class, program))
+}
+
+/// Returns the file name of the program that an `Exec` key executes.
+fn program(exec: &str) -> Option<String> {
+    let mut args = exec
+        .split_whitespace()
+        .map(|a| a.trim_matches('"'))
+        .skip_while(|a| *a == "env" || a.contains('='));
+    let program = args.next()?;
+    let name = Path::new(program).file_name()?.to_str()?;
+    if name == "flatpak" {
+        if let Some(command) = args.find_map(|a| a.strip_prefix("--command=")) {
+            return Some(command.to_string());
+        }
+    }
+    Some(name.to_string())
+}
+
+static APPLICATION_DIRS: LazyLock<Vec<PathBuf>> = LazyLock::new(|| {
+    let mut dirs = vec![];
+    if let Ok(data_home) = var("XDG_DATA_HOME") {
+        dirs.push(format!("{data_home}/applications"));
+    } else {
+        dirs.push("$HOME/.local/share/applications".to_string());
+    }
+    if let Ok(data_dirs) = var("XDG_DATA_DIRS") {
+        for dir in data_dirs.split(":") {
+            dirs.push(format!("{dir}/applications"));
+        }
+    } else {
+        dirs.push("/usr/local/share/applications".to_string());
+        dirs.push("/usr/share/applications".to_string());
+    }
+    dirs.into_iter()
+        .flat_map(|d| shellexpand::full(&d).ok().map(|s| s.into_owned()))
+        .map(PathBuf::from)
+        .collect()
+});
+
+#[test]
+fn programs() {
+    assert_eq!(program("nm-applet").as_deref(), Some("nm-applet"));
+    assert_eq!(
+        program("env FOO=1 \"/usr/bin/telegram-desktop\" -- %u").as_deref(),
+        Some("telegram-desktop"),
+    );
+    assert_eq!(
+        program("/usr/bin/flatpak run --branch=stable --command=discord com.discordapp.Discord")
+            .as_deref(),
+        Some("discord"),
+    );
+}
+
+#[test]
+fn find_precedence() {
+    let entry = |name: &str| {
+        Arc::new(DesktopEntry {
+            name: Some(Arc::new(name.to_string())),
+            icon: None,
+        })
+    };
+    let name = |index: &Index, keys: &[&str]| {
+        let entry = index.find(keys.iter().copied())?;
+        entry.name.as_deref().cloned()
+    };
+    let mut index = Index::default();
+    for (map, name) in [
+        (&mut index.ids, "id"),
+        (&mut index.short_ids, "short-id"),
+        (&mut index.wm_classes, "wm-class"),
+        (&mut index.programs, "program"),
+    ] {
+        map.insert("app".to_string(), entry(name));
+        map.insert(name.to_string(), entry(name));
+    }
+    assert_eq!(name(&index, &["App"]).as_deref(), Some("id"));
+    index.ids.remove("app");
+    assert_eq!(name(&index, &["app"]).as_deref(), Some("short-id"));
+    index.short_ids.remove("app");
+    assert_eq!(name(&index, &["app"]).as_deref(), Some("wm-class"));
+    index.wm_classes.remove("app");
+    assert_eq!(name(&index, &["app"]).as_deref(), Some("program"));
+    // Earlier keys take precedence over earlier maps.
+    assert_eq!(name(&index, &["program", "id"]).as_deref(), Some("program"));
+    assert_eq!(name(&index, &["unknown"]), None);
+}
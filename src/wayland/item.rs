@@ -1,10 +1,20 @@
 use {
     crate::{
-        sni::{SniItem, SniItemId, SniItemProperties},
-        wayland::tray::item::{icon::IconTemplate, menu::Menu},
+        settings::{self, ItemOverrides},
+        sni::{IconFrames, SniItem, SniItemId, SniItemProperties},
+        wayland::{
+            desktop::{self, DesktopEntry},
+            sni_proxy::EventSink,
+            tray::item::{
+                icon::IconTemplate,
+                menu::Menu,
+                movie::{load_movie, Movie},
+            },
+        },
     },
     ahash::AHashMap,
-    std::sync::Arc,
+    isnt::std_1::string::IsntStringExt,
+    std::{sync::Arc, thread},
 };
 
 #[derive(Default)]
@@ -12,21 +22,96 @@ pub struct Items {
     pub items: AHashMap<SniItemId, Item>,
 }
 
+/// The `AttentionMovieName` of an item and its `IconThemePath`.
+pub type MovieSource = (Arc<String>, Option<Arc<String>>);
+
 pub struct Item {
     pub sni: Arc<SniItem>,
+    pub sink: EventSink,
     pub props: SniItemProperties,
     pub icon: IconTemplate,
     pub attention_icon: IconTemplate,
+    pub overlay_icon: IconTemplate,
+    pub attention_movie: Option<Arc<Movie>>,
+    /// The source of `attention_movie`. The movie is loaded in the background, so
+    /// `attention_movie` might still belong to the previous source.
+    pub attention_movie_source: Option<MovieSource>,
+    pub tooltip_icon: IconTemplate,
     pub menu: Menu,
+    pub overrides: ItemOverrides,
+    pub desktop_entry: Option<Arc<DesktopEntry>>,
+    /// Whether the item was hidden via the control interface.
+    pub hidden: bool,
 }
 
 impl Item {
     pub fn initialize(&mut self) {
+        self.update_desktop_entry();
+        self.update_overrides();
+        self.update_icon();
+        self.update_attention_icon();
+        self.update_overlay_icon();
+        self.update_tooltip();
+    }
+
+    pub fn is_hidden(&self) -> bool {
+        self.hidden || self.overrides.hide
+    }
+
+    /// Re-evaluates the `[[item]]` rules. Returns whether the overrides changed.
+    pub fn update_overrides(&mut self) -> bool {
+        let overrides = settings::get().item_overrides(&self.props, self.sni.bus_name());
+        if overrides == self.overrides {
+            return false;
+        }
+        self.overrides = overrides;
+        true
+    }
+
+    /// Applies new settings. Returns whether the overrides changed.
+    pub fn handle_settings_changed(&mut self, theme_changed: bool) -> bool {
+        if theme_changed {
+            self.attention_movie_source = None;
+        }
+        let changed = self.update_overrides();
         self.update_icon();
         self.update_attention_icon();
+        changed
+    }
+
+    /// Looks up the desktop entry by the id, the bus name, and the executable. Returns
+    /// whether the entry changed.
+    pub fn update_desktop_entry(&mut self) -> bool {
+        let keys = [
+            self.props.id.as_deref().map(String::as_str),
+            Some(self.sni.bus_name()),
+            self.props.executable.as_deref().map(String::as_str),
+        ];
+        let entry = desktop::find(keys.into_iter().flatten());
+        let changed = entry != self.desktop_entry;
+        self.desktop_entry = entry;
+        changed
+    }
+
+    /// Returns the title of the item or the name of its desktop entry.
+    pub fn title(&self) -> Option<&Arc<String>> {
+        let name = || self.desktop_entry.as_ref()?.name.as_ref();
+        self.props
+            .title
+            .as_ref()
+            .filter(|t| t.is_not_empty())
+            .or_else(name)
     }
 
     pub fn update_icon(&mut self) {
+        let fallback = self.desktop_entry.as_ref().and_then(|e| e.icon.as_ref());
+        let avatar = self.title().or(self.props.id.as_ref()).cloned();
+        self.icon.update_fallback(fallback, avatar.as_ref());
+        if let Some(icon) = &self.overrides.icon {
+            self.icon.update_name(Some(icon), None);
+            self.icon.update_frames(None);
+            return;
+        }
         self.icon.update_name(
             self.props.icon_name.as_ref(),
             self.props.icon_theme_path.as_ref(),
@@ -41,5 +126,46 @@ impl Item {
         );
         self.attention_icon
             .update_frames(self.props.attention_icon.as_ref());
+        let source = self
+            .props
+            .attention_movie_name
+            .as_ref()
+            .filter(|n| n.is_not_empty())
+            .map(|n| (n.clone(), self.props.icon_theme_path.clone()));
+        if source == self.attention_movie_source {
+            return;
+        }
+        self.attention_movie_source = source.clone();
+        let Some((name, path)) = source else {
+            self.attention_movie = None;
+            return;
+        };
+        let id = self.sni.id();
+        let theme = settings::get().theme.clone();
+        let sink = self.sink.clone();
+        thread::spawn(move || {
+            let movie = load_movie(&name, path.as_deref().map(|p| &**p), &theme).map(Arc::new);
+            sink.send(move |state| state.handle_movie_loaded(id, (name, path), movie));
+        });
+    }
+
+    pub fn update_overlay_icon(&mut self) {
+        self.overlay_icon.update_name(
+            self.props.overlay_icon_name.as_ref(),
+            self.props.icon_theme_path.as_ref(),
+        );
+        self.overlay_icon
+            .update_frames(self.props.overlay_icon.as_ref());
+    }
+
+    pub fn update_tooltip(&mut self) {
+        let tooltip = self.props.tooltip.as_ref();
+        let name = tooltip
+            .filter(|t| t.icon_name.is_not_empty())
+            .map(|t| Arc::new(t.icon_name.clone()));
+        self.tooltip_icon
+            .update_name(name.as_ref(), self.props.icon_theme_path.as_ref());
+        let frames = tooltip.map(|t| IconFrames::from(t.icon_data.clone()));
+        self.tooltip_icon.update_frames(frames.as_ref());
     }
 }
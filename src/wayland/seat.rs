@@ -1,15 +1,21 @@
 use {
     crate::wayland::{
         item::Items,
-        tray::{item::menu::MenuId, TraySurfaceId, Trays},
+        tray::{
+            item::menu::{MenuId, MenuKey},
+            TraySurfaceId, Trays,
+        },
         Singletons,
     },
+    error_reporter::Report,
     std::{
+        os::fd::OwnedFd,
         sync::atomic::{AtomicUsize, Ordering::Relaxed},
         time::Duration,
     },
     tokio::task::JoinHandle,
     wayland_client::protocol::{
+        wl_keyboard::{KeymapFormat, WlKeyboard},
         wl_pointer::{Axis, WlPointer},
         wl_seat::{Capability, WlSeat},
         wl_surface::WlSurface,
@@ -17,17 +23,21 @@ use {
     wayland_protocols::wp::cursor_shape::v1::client::wp_cursor_shape_device_v1::{
         Shape, WpCursorShapeDeviceV1,
     },
+    xkbcommon::xkb::{self, keysyms},
 };
 
 pub struct Seat {
     name: u32,
     seat: WlSeat,
     pointer: Option<Pointer>,
+    keyboard: Option<Keyboard>,
     focus: Option<TraySurfaceId>,
+    keyboard_focus: Option<TraySurfaceId>,
     x: i32,
     y: i32,
     scroll: [i32; 2],
     timeout: Option<Timeout>,
+    last_serial: Option<u32>,
 }
 
 struct Pointer {
@@ -42,6 +52,17 @@ impl Drop for Pointer {
     }
 }
 
+struct Keyboard {
+    keyboard: WlKeyboard,
+    state: Option<xkb::State>,
+}
+
+impl Drop for Keyboard {
+    fn drop(&mut self) {
+        self.keyboard.release();
+    }
+}
+
 struct Timeout {
     id: usize,
     target: MotionTimeoutTarget,
@@ -73,11 +94,14 @@ impl Seat {
             name,
             seat,
             pointer: None,
+            keyboard: None,
             focus: None,
+            keyboard_focus: None,
             x: 0,
             y: 0,
             scroll: [0; 2],
             timeout: None,
+            last_serial: None,
         }
     }
 
@@ -89,6 +113,11 @@ impl Seat {
         &self.seat
     }
 
+    /// Returns the serial of the last pointer enter or button event on our surfaces.
+    pub fn last_serial(&self) -> Option<u32> {
+        self.last_serial
+    }
+
     pub fn update_capabilities(
         &mut self,
         s: &Singletons,
@@ -109,6 +138,100 @@ impl Seat {
                 self.handle_pointer_leave(trays);
             }
         }
+        let want_keyboard = capabilities.contains(Capability::Keyboard);
+        if want_keyboard {
+            if self.keyboard.is_none() {
+                let keyboard = self.seat.get_keyboard(&s.qh, self.name);
+                self.keyboard = Some(Keyboard {
+                    keyboard,
+                    state: None,
+                });
+            }
+        } else {
+            if self.keyboard.take().is_some() {
+                self.keyboard_focus = None;
+            }
+        }
+    }
+
+    pub fn handle_keymap(&mut self, format: KeymapFormat, fd: OwnedFd, size: u32) {
+        let Some(keyboard) = &mut self.keyboard else {
+            return;
+        };
+        keyboard.state = None;
+        if format != KeymapFormat::XkbV1 {
+            return;
+        }
+        let context = xkb::Context::new(xkb::CONTEXT_NO_FLAGS);
+        let keymap = unsafe {
+            xkb::Keymap::new_from_fd(
+                &context,
+                fd,
+                size as usize,
+                xkb::KEYMAP_FORMAT_TEXT_V1,
+                xkb::KEYMAP_COMPILE_NO_FLAGS,
+            )
+        };
+        match keymap {
+            Ok(Some(keymap)) => keyboard.state = Some(xkb::State::new(&keymap)),
+            Ok(None) => log::error!("Could not compile the keymap"),
+            Err(e) => log::error!("Could not map the keymap: {}", Report::new(e)),
+        }
+    }
+
+    pub fn handle_keyboard_enter(&mut self, trays: &Trays, surface: &WlSurface) {
+        self.keyboard_focus = trays.find_surface(surface);
+    }
+
+    pub fn handle_keyboard_leave(&mut self) {
+        self.keyboard_focus = None;
+    }
+
+    pub fn handle_modifiers(&mut self, depressed: u32, latched: u32, locked: u32, group: u32) {
+        let Some(state) = self.keyboard.as_mut().and_then(|k| k.state.as_mut()) else {
+            return;
+        };
+        state.update_mask(depressed, latched, locked, 0, 0, group);
+    }
+
+    pub fn handle_key_pressed(
+        &mut self,
+        items: &Items,
+        s: &Singletons,
+        trays: &mut Trays,
+        key: u32,
+    ) {
+        let Some(state) = self.keyboard.as_ref().and_then(|k| k.state.as_ref()) else {
+            return;
+        };
+        let code = xkb::Keycode::new(key + 8);
+        let key = match state.key_get_one_sym(code).raw() {
+            keysyms::KEY_Up | keysyms::KEY_KP_Up => MenuKey::Up,
+            keysyms::KEY_Down | keysyms::KEY_KP_Down => MenuKey::Down,
+            keysyms::KEY_Home | keysyms::KEY_KP_Home => MenuKey::Home,
+            keysyms::KEY_End | keysyms::KEY_KP_End => MenuKey::End,
+            keysyms::KEY_Left | keysyms::KEY_KP_Left => MenuKey::Left,
+            keysyms::KEY_Right | keysyms::KEY_KP_Right => MenuKey::Right,
+            keysyms::KEY_Return
+            | keysyms::KEY_KP_Enter
+            | keysyms::KEY_space
+            | keysyms::KEY_KP_Space => MenuKey::Activate,
+            keysyms::KEY_Escape => MenuKey::Escape,
+            _ => {
+                let c = char::from_u32(state.key_get_utf32(code));
+                let Some(c) = c.filter(|c| !c.is_control()) else {
+                    return;
+                };
+                MenuKey::Char(c)
+            }
+        };
+        let Some(focus) = self.keyboard_focus else {
+            return;
+        };
+        let Some(item) = items.items.get(&focus.item.item) else {
+            return;
+        };
+        trays.handle_key(self, focus, s, item, key);
     }
 
     pub fn handle_pointer_leave(&mut self, trays: &mut Trays) {
@@ -130,6 +253,7 @@ impl Seat {
         serial: u32,
     ) {
         self.handle_pointer_leave(trays);
+        self.last_serial = Some(serial);
         if let Some(pointer) = &self.pointer {
             pointer.shape.set_shape(serial, Shape::Default);
         }
@@ -201,6 +325,7 @@ impl Seat {
         button: u32,
         serial: u32,
     ) {
+        self.last_serial = Some(serial);
         let Some(id) = self.focus else {
             return;
         };
@@ -210,7 +335,14 @@ impl Seat {
         trays.handle_button(self, serial, id, s, item, button);
     }
 
-    pub fn handle_axis_value120(&mut self, trays: &mut Trays, axis: Axis, value120: i32) {
+    pub fn handle_axis_value120(
+        &mut self,
+        s: &Singletons,
+        trays: &mut Trays,
+        items: &Items,
+        axis: Axis,
+        value120: i32,
+    ) {
         let accu = &mut self.scroll[axis as usize];
         *accu += value120;
         let steps = *accu / 120;
@@ -218,10 +350,14 @@ impl Seat {
         let Some(focus) = self.focus else {
             return;
         };
-        trays.handle_scroll(focus, axis, steps);
+        let Some(item) = items.items.get(&focus.item.item) else {
+            return;
+        };
+        trays.handle_scroll(self, focus, s, item, axis, steps);
     }
 
     pub fn handle_remove(&mut self, trays: &mut Trays) {
+        self.keyboard_focus = None;
         if let Some(focus) = self.focus {
             trays.handle_leave(self, focus);
         }
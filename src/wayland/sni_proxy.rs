@@ -40,9 +40,9 @@ impl EventStream {
     }
 }
 
-pub fn spawn(conn: &Arc<bussy::Connection>, sink: &EventSink) {
+pub fn spawn(conn: &Arc<bussy::Connection>, watcher: bool, sink: &EventSink) {
     let sink = sink.clone();
-    sni::spawn(conn, move |item| handle_new_item(&sink, item))
+    sni::spawn(conn, watcher, move |item| handle_new_item(&sink, item))
 }
 
 type Action = Box<dyn FnOnce(&mut State) + Send>;
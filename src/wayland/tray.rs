@@ -1,13 +1,13 @@
 use {
     crate::{
-        sni::{MutableProperty, SniItemId, SniMenuDelta},
+        sni::{MutableProperty, SniItem, SniItemId, SniMenuDelta},
         wayland::{
             item::Items,
             scale::{Logical, Scale},
             seat::{MotionResult, Seat},
             tray::{
                 item::{
-                    menu::{MenuId, MenuInstance},
+                    menu::{MenuId, MenuInstance, MenuKey},
                     TrayItem,
                 },
                 protocols::{ProtoName, WaylandTray},
@@ -16,8 +16,14 @@ use {
         },
     },
     ahash::AHashMap,
-    wayland_client::protocol::{wl_buffer::WlBuffer, wl_pointer::Axis, wl_surface::WlSurface},
-    wayland_protocols::xdg::shell::client::xdg_positioner::{Anchor, Gravity},
+    std::{cmp::Reverse, fmt::Write, sync::Arc},
+    wayland_client::protocol::{
+        wl_buffer::WlBuffer, wl_callback::WlCallback, wl_pointer::Axis, wl_surface::WlSurface,
+    },
+    wayland_protocols::xdg::{
+        activation::v1::client::xdg_activation_token_v1::XdgActivationTokenV1,
+        shell::client::xdg_positioner::{Anchor, Gravity},
+    },
 };
 
 pub mod item;
@@ -43,8 +49,9 @@ pub struct TrayItemId {
 
 pub struct Tray {
     name: u32,
-    tray: Box<dyn WaylandTray>,
     items: AHashMap<SniItemId, TrayItem>,
+    tray: Box<dyn WaylandTray>,
+    next_seq: u64,
 }
 
 #[derive(Default)]
@@ -62,20 +69,49 @@ impl Trays {
                     self.trays.clear();
                 }
             }
+            ProtoName::WlrLayerShell => {}
         }
         self.trays.entry(name).or_insert(Tray {
             name,
             tray,
             items: Default::default(),
+            next_seq: 0,
         })
     }
 
+    pub fn has_ext_tray(&self) -> bool {
+        self.has_ext_tray
+    }
+
     pub fn add_item(&mut self, singletons: &Singletons, item: &Item) {
         for tray in self.trays.values_mut() {
             tray.add_item(singletons, item);
         }
     }
 
+    /// Positions the items in trays that do not leave this to the compositor.
+    pub fn arrange(&mut self, items: &Items, s: &Singletons) {
+        for tray in self.trays.values_mut() {
+            tray.arrange(items, s);
+        }
+    }
+
+    pub fn handle_panel_configure(
+        &mut self,
+        items: &Items,
+        s: &Singletons,
+        name: u32,
+        serial: u32,
+        width: u32,
+        height: u32,
+    ) {
+        let Some(tray) = self.trays.get_mut(&name) else {
+            return;
+        };
+        tray.tray.handle_configure(serial, width, height);
+        tray.arrange(items, s);
+    }
+
     pub fn handle_item_prop_changed(&mut self, s: &Singletons, item: &Item, prop: MutableProperty) {
         for tray in self.trays.values_mut() {
             if let Some(tray_item) = tray.items.get_mut(&item.sni.id()) {
@@ -84,6 +120,22 @@ impl Trays {
         }
     }
 
+    pub fn handle_settings_changed(&mut self, items: &Items, s: &Singletons) {
+        for tray in self.trays.values_mut() {
+            for tray_item in tray.items.values_mut() {
+                if let Some(item) = items.items.get(&tray_item.id.item) {
+                    tray_item.handle_settings_changed(s, item);
+                }
+            }
+        }
+    }
+
+    /// Removes the layer-shell panels.
+    pub fn remove_panels(&mut self) {
+        self.trays
+            .retain(|_, tray| tray.tray.proto_name() != ProtoName::WlrLayerShell);
+    }
+
     pub fn handle_item_removed(&mut self, item: SniItemId) {
         for tray in self.trays.values_mut() {
             tray.items.remove(&item);
@@ -144,11 +196,19 @@ impl Trays {
         item.handle_timeout(seat, items, s, surface.menu, menu_id)
     }
 
-    pub fn handle_scroll(&mut self, surface: TraySurfaceId, axis: Axis, steps: i32) {
-        let Some(item) = self.get_item_mut(surface.item) else {
+    pub fn handle_scroll(
+        &mut self,
+        seat: &Seat,
+        surface: TraySurfaceId,
+        s: &Singletons,
+        item: &Item,
+        axis: Axis,
+        steps: i32,
+    ) {
+        let Some(tray_item) = self.get_item_mut(surface.item) else {
             return;
         };
-        item.handle_scroll(surface.menu, axis, steps);
+        tray_item.handle_scroll(seat, surface.menu, s, item, axis, steps);
     }
 
     pub fn handle_menu_changed(&mut self, s: &Singletons, item: &Item, delta: &SniMenuDelta) {
@@ -163,6 +223,23 @@ impl Trays {
         }
     }
 
+    pub fn handle_frame_done(&mut self, s: &Singletons, id: TrayItemId, callback: &WlCallback) {
+        let Some(tray_item) = self.get_item_mut(id) else {
+            return;
+        };
+        tray_item.handle_frame_done(s, callback);
+    }
+
+    pub fn handle_animation_tick(&mut self, items: &Items, s: &Singletons, id: TrayItemId) {
+        let Some(item) = items.items.get(&id.item) else {
+            return;
+        };
+        let Some(tray_item) = self.get_item_mut(id) else {
+            return;
+        };
+        tray_item.handle_animation_tick(s, item);
+    }
+
     pub fn handle_popup_repositioned(&mut self, id: PopupId, token: u32) {
         let Some(item) = self.get_item_mut(id.tray_item) else {
             return;
@@ -194,6 +271,35 @@ impl Trays {
         item.handle_popup_configure(id.ty, serial);
     }
 
+    pub fn handle_popup_geometry(
+        &mut self,
+        items: &Items,
+        s: &Singletons,
+        id: PopupId,
+        height: i32,
+    ) {
+        let Some(item) = items.items.get(&id.tray_item.item) else {
+            return;
+        };
+        let Some(tray_item) = self.get_item_mut(id.tray_item) else {
+            return;
+        };
+        tray_item.handle_popup_geometry(s, item, id.ty, height);
+    }
+
+    pub fn handle_activation_token(
+        &mut self,
+        s: &Singletons,
+        id: TrayItemId,
+        token: &XdgActivationTokenV1,
+        value: &str,
+    ) {
+        let Some(tray_item) = self.get_item_mut(id) else {
+            return;
+        };
+        tray_item.handle_activation_token(s, token, value);
+    }
+
     pub fn handle_popup_done(&mut self, id: PopupId) {
         let Some(item) = self.get_item_mut(id.tray_item) else {
             return;
@@ -222,6 +328,20 @@ impl Trays {
         }
     }
 
+    pub fn handle_key(
+        &mut self,
+        seat: &Seat,
+        id: TraySurfaceId,
+        s: &Singletons,
+        item: &Item,
+        key: MenuKey,
+    ) {
+        let Some(tray_item) = self.get_item_mut(id.item) else {
+            return;
+        };
+        tray_item.handle_key(seat, id.menu, s, item, key);
+    }
+
     pub fn open_menu(
         &mut self,
         seat: &Seat,
@@ -259,6 +379,41 @@ impl Trays {
         tray_item.open_root_menu(seat, s, item);
     }
 
+    /// Opens the root menu of the item in the first tray that shows it.
+    ///
+    /// Returns whether the item was found.
+    pub fn open_menu_with_serial(
+        &mut self,
+        seat: &Seat,
+        serial: u32,
+        s: &Singletons,
+        item: &Item,
+    ) -> bool {
+        let tray_item = self
+            .trays
+            .values_mut()
+            .find_map(|t| t.items.get_mut(&item.sni.id()));
+        let Some(tray_item) = tray_item else {
+            return false;
+        };
+        tray_item.menu = None;
+        tray_item.seat_serials.insert(seat.name(), serial);
+        tray_item.open_root_menu(seat, s, item);
+        true
+    }
+
+    pub fn dump(&self, res: &mut String) {
+        for tray in self.trays.values() {
+            let _ = writeln!(
+                res,
+                "  {}: protocol={:?} items={}",
+                tray.name,
+                tray.tray.proto_name(),
+                tray.items.len(),
+            );
+        }
+    }
+
     pub fn handle_global_remove(&mut self, name: u32) {
         self.trays.remove(&name);
     }
@@ -280,11 +435,58 @@ impl Tray {
         None
     }
 
+    /// Adds the item unless it is hidden.
+    ///
+    /// Compositors show items in the order in which they were created. Items with a
+    /// lower priority are therefore re-created after the new item.
     pub fn add_item(&mut self, s: &Singletons, item: &Item) {
+        if item.is_hidden() {
+            return;
+        }
+        let priority = item.overrides.priority;
+        let mut later: Vec<_> = self
+            .items
+            .values()
+            .filter(|i| i.priority < priority)
+            .map(|i| (Reverse(i.priority), i.seq, i.sni.id()))
+            .collect();
+        later.sort_unstable_by_key(|&(priority, seq, _)| (priority, seq));
+        self.create_item(s, &item.sni, priority);
+        for (_, _, id) in later {
+            if let Some(old) = self.items.remove(&id) {
+                let (sni, priority) = (old.sni.clone(), old.priority);
+                drop(old);
+                self.create_item(s, &sni, priority);
+            }
+        }
+    }
+
+    fn arrange(&mut self, items: &Items, s: &Singletons) {
+        let mut tray_items: Vec<_> = self.items.values_mut().collect();
+        tray_items.sort_unstable_by_key(|i| i.seq);
+        let wayland_items: Vec<_> = tray_items.iter().map(|i| &*i.item).collect();
+        let Some(layout) = self.tray.arrange(&wayland_items) else {
+            return;
+        };
+        for tray_item in tray_items {
+            let Some(item) = items.items.get(&tray_item.id.item) else {
+                continue;
+            };
+            tray_item.configure_size(layout.size);
+            tray_item.set_preferred_anchor(layout.anchor);
+            tray_item.set_preferred_gravity(layout.gravity);
+            // The serial is ignored by trays that position items themselves.
+            tray_item.configure(Some(0), s, item);
+        }
+    }
+
+    fn create_item(&mut self, s: &Singletons, sni: &Arc<SniItem>, priority: i32) {
         let id = TrayItemId {
             tray: self.name,
-            item: item.sni.id(),
+            item: sni.id(),
         };
+        let seq = self.next_seq;
+        self.next_seq += 1;
         let surface = s.wl_compositor.create_surface(&s.qh, id);
         let fractional_scale = s
             .wp_fractional_scale_manager_v1
@@ -293,7 +495,7 @@ impl Tray {
         let viewport = s.wp_viewporter.get_viewport(&surface, &s.qh, ());
         let ext_item = self.tray.get_tray_item(&surface, &s.qh, id);
         self.items.insert(
-            item.sni.id(),
+            sni.id(),
             TrayItem {
                 id,
                 surface,
@@ -303,14 +505,20 @@ impl Tray {
                 size: Logical(0, 0),
                 preferred_anchor: Anchor::None,
                 preferred_gravity: Gravity::None,
-                sni: item.sni.clone(),
+                sni: sni.clone(),
+                priority,
+                seq,
                 tooltip: None,
                 scale: Scale(120),
                 buffers: Default::default(),
+                blink_buffers: Default::default(),
+                movie_buffers: Default::default(),
+                animation: Default::default(),
                 menu: None,
                 seat_serials: Default::default(),
                 seat_positions: Default::default(),
                 current_activation: None,
+                pending_activation: None,
                 fractional_scale,
             },
         );
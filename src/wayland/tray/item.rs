@@ -1,6 +1,6 @@
 use {
     crate::{
-        settings::{self},
+        settings::{self, ClickAction, LabelMode},
         sni::{MutableProperty, SniItem},
         wayland::{
             item::Items,
@@ -8,9 +8,12 @@ use {
             seat::{MotionResult, MotionTimeoutTarget, Seat},
             tray::{
                 item::{
+                    animation::Animation,
                     icon::BufferIcon,
-                    menu::{MenuId, MenuInstance},
-                    tooltip::{create_tooltip, Tooltip},
+                    label::Label,
+                    menu::{MenuId, MenuInstance, MenuKey, MenuKeyResult},
+                    movie::MovieBuffers,
+                    tooltip::{create_tooltip, Tooltip, TooltipContent},
                 },
                 protocols::WaylandTrayItem,
                 PopupId, PopupIdType, TraySurfaceId,
@@ -23,22 +26,31 @@ use {
     error_reporter::Report,
     isnt::std_1::string::IsntStringExt,
     std::{sync::Arc, time::Duration},
-    wayland_client::protocol::{wl_buffer::WlBuffer, wl_pointer::Axis, wl_surface::WlSurface},
+    wayland_client::protocol::{
+        wl_buffer::WlBuffer, wl_callback::WlCallback, wl_pointer::Axis, wl_seat::WlSeat,
+        wl_surface::WlSurface,
+    },
     wayland_protocols::{
         wp::{
             fractional_scale::v1::client::wp_fractional_scale_v1::WpFractionalScaleV1,
             viewporter::client::wp_viewport::WpViewport,
         },
-        xdg::shell::client::{
-            xdg_popup::XdgPopup,
-            xdg_positioner::{Anchor, ConstraintAdjustment, Gravity},
-            xdg_surface::XdgSurface,
+        xdg::{
+            activation::v1::client::xdg_activation_token_v1::XdgActivationTokenV1,
+            shell::client::{
+                xdg_popup::XdgPopup,
+                xdg_positioner::{Anchor, ConstraintAdjustment, Gravity},
+                xdg_surface::XdgSurface,
+            },
         },
     },
 };
 
+pub mod animation;
 pub mod icon;
+pub mod label;
 pub mod menu;
+pub mod movie;
 pub mod tooltip;
 
 #[derive(Default)]
@@ -53,6 +65,8 @@ pub struct TrayItem {
     pub(super) surface: WlSurface,
     pub(super) fractional_scale: Option<WpFractionalScaleV1>,
     pub(super) sni: Arc<SniItem>,
+    pub(super) priority: i32,
+    pub(super) seq: u64,
     pub(super) viewport: WpViewport,
     pub(super) item: Box<dyn WaylandTrayItem>,
     pub(super) pending: TrayItemPending,
@@ -62,10 +76,14 @@ pub struct TrayItem {
     pub(super) tooltip: Option<TrayItemPopup>,
     pub(super) scale: Scale,
     pub(super) buffers: BufferIcon,
+    pub(super) blink_buffers: BufferIcon,
+    pub(super) movie_buffers: MovieBuffers,
+    pub(super) animation: Animation,
     pub(super) menu: Option<MenuInstance>,
     pub(super) seat_serials: AHashMap<u32, u32>,
     pub(super) seat_positions: AHashMap<u32, (i32, i32)>,
     pub(super) current_activation: Option<Call>,
+    pub(super) pending_activation: Option<PendingActivation>,
 }
 
 impl Drop for TrayItem {
@@ -81,8 +99,26 @@ impl Drop for TrayItem {
     }
 }
 
+/// An activation that is waiting for an xdg-activation token.
+pub struct PendingActivation {
+    token: XdgActivationTokenV1,
+    secondary: bool,
+    x: i32,
+    y: i32,
+    had_menu: bool,
+    seat_name: u32,
+}
+
+impl Drop for PendingActivation {
+    fn drop(&mut self) {
+        self.token.destroy();
+    }
+}
+
 pub struct TrayItemPopup {
     tooltip: Tooltip,
+    wl_seat: WlSeat,
+    seat_name: u32,
     xdg_surface: XdgSurface,
     xdg_popup: XdgPopup,
 }
@@ -124,24 +160,84 @@ impl TrayItem {
         if self.size.0 == 0 || self.size.1 == 0 {
             return;
         }
-        self.buffers.update(
-            match item.props.status.as_ref().map(|v| &***v) == Some("NeedsAttention") {
-                true => &item.attention_icon,
-                false => &item.icon,
-            },
-            self.size.to_physical(self.scale).size(),
-            self.scale.round_up(),
-            &settings::get().theme,
-            &settings::get().icon.color,
-            singletons,
-        );
-        let buffer = self.buffers.get();
+        let settings = settings::get();
+        let size = self.size.to_physical(self.scale).size();
+        let scale = self.scale.round_up();
+        let needs_attention = item.props.status.as_ref().map(|v| &***v) == Some("NeedsAttention");
+        let blink_interval = settings.icon.attention_blink_interval;
+        let label = item
+            .props
+            .label
+            .as_ref()
+            .filter(|l| l.is_not_empty() && settings.label.mode != LabelMode::None)
+            .map(|text| Label {
+                text: text.clone(),
+                guide: item.props.label_guide.clone(),
+                scale: self.scale.to_f64() * settings.scale,
+                settings: settings.label.clone(),
+            });
+        let update_buffers = |buffers: &mut BufferIcon, template| {
+            buffers.update(
+                template,
+                &item.overlay_icon,
+                label.as_ref(),
+                size,
+                scale,
+                &settings.theme,
+                item.overrides
+                    .color
+                    .as_ref()
+                    .unwrap_or(&settings.icon.color),
+                singletons,
+            );
+        };
+        let (buffer, num_frames, delay) = if !needs_attention {
+            update_buffers(&mut self.buffers, &item.icon);
+            (self.buffers.get(), 1, Duration::ZERO)
+        } else if let Some(movie) = &item.attention_movie {
+            self.movie_buffers.update(movie, singletons);
+            let frame = self.animation.frame % movie.frames.len();
+            (
+                self.movie_buffers.get(frame),
+                movie.frames.len(),
+                movie.frames[frame].delay,
+            )
+        } else if blink_interval > 0.0 {
+            update_buffers(&mut self.buffers, &item.attention_icon);
+            update_buffers(&mut self.blink_buffers, &item.icon);
+            let buffer = match self.animation.frame % 2 {
+                0 => self.buffers.get(),
+                _ => self.blink_buffers.get(),
+            };
+            let interval =
+                Duration::try_from_secs_f64(blink_interval).unwrap_or(Duration::from_secs(1));
+            (buffer, 2, interval)
+        } else {
+            update_buffers(&mut self.buffers, &item.attention_icon);
+            (self.buffers.get(), 1, Duration::ZERO)
+        };
         self.viewport.set_destination(self.size.0, self.size.1);
         self.surface.attach(buffer.map(|b| &b.0.buffer), 0, 0);
         self.surface.damage_buffer(0, 0, i32::MAX, i32::MAX);
+        if num_frames > 1 {
+            self.animation
+                .schedule(singletons, &self.surface, self.id, delay);
+        } else {
+            self.animation.stop();
+        }
         self.surface.commit();
     }
 
+    pub fn handle_frame_done(&mut self, s: &Singletons, callback: &WlCallback) {
+        self.animation.handle_frame_done(s, callback, self.id);
+    }
+
+    pub fn handle_animation_tick(&mut self, s: &Singletons, item: &Item) {
+        if self.animation.handle_tick() {
+            self.configure(None, s, item);
+        }
+    }
+
     pub fn handle_button(
         &mut self,
         seat: &Seat,
@@ -156,7 +252,7 @@ impl TrayItem {
         if let Some(menu_id) = menu {
             if let Some(menu) = &mut self.menu {
                 #[expect(clippy::collapsible_if)]
-                if menu.handle_button(seat, &item.menu, menu_id) {
+                if menu.handle_button(seat, &item.menu, s, menu_id) {
                     if !settings::get().keep_open {
                         self.menu = None;
                     }
@@ -167,32 +263,135 @@ impl TrayItem {
             const BTN_RIGHT: u32 = 0x111;
             const BTN_MIDDLE: u32 = 0x112;
             let had_menu = self.menu.take().is_some();
-            if button == BTN_LEFT || button == BTN_MIDDLE {
-                let sink = s.sink.clone();
-                let id = self.id;
-                let seat_name = seat.name();
-                let cb = move |ok: bool| {
-                    if !ok && !had_menu {
-                        sink.send(move |state| {
-                            state.open_root_menu(seat_name, id);
-                        });
-                    }
-                };
-                let activation = if button == BTN_LEFT {
-                    self.sni.activate(cb)
-                } else {
-                    self.sni.secondary_activate(cb)
-                };
-                self.current_activation = Some(activation);
+            let (x, y) = self.position(seat);
+            let overrides = &item.overrides;
+            let action = match button {
+                BTN_LEFT => overrides.left_click.unwrap_or(match item.props.is_menu {
+                    true => ClickAction::Menu,
+                    false => ClickAction::Activate,
+                }),
+                BTN_MIDDLE => overrides
+                    .middle_click
+                    .unwrap_or(ClickAction::SecondaryActivate),
+                BTN_RIGHT => overrides.right_click.unwrap_or(ClickAction::Menu),
+                _ => return,
+            };
+            if let ClickAction::Activate | ClickAction::SecondaryActivate = action {
+                let secondary = action == ClickAction::SecondaryActivate;
+                self.pending_activation = None;
+                if let Some(xdg_activation) = &s.xdg_activation_v1 {
+                    let token = xdg_activation.get_activation_token(&s.qh, self.id);
+                    token.set_serial(serial, seat.wl_seat());
+                    token.set_surface(&self.surface);
+                    token.commit();
+                    self.pending_activation = Some(PendingActivation {
+                        token,
+                        secondary,
+                        x,
+                        y,
+                        had_menu,
+                        seat_name: seat.name(),
+                    });
+                    return;
+                }
+                self.activate(s, seat.name(), secondary, x, y, had_menu);
                 return;
             }
-            if button == BTN_RIGHT && !had_menu {
-                self.open_root_menu(seat, s, item);
+            match action {
+                ClickAction::Menu if !had_menu => self.open_root_menu(seat, s, item),
+                ClickAction::ContextMenu => self.sni.context_menu(x, y),
+                _ => {}
             }
         }
     }
 
+    fn activate(
+        &mut self,
+        s: &Singletons,
+        seat_name: u32,
+        secondary: bool,
+        x: i32,
+        y: i32,
+        had_menu: bool,
+    ) {
+        let sink = s.sink.clone();
+        let id = self.id;
+        let cb = move |ok: bool| {
+            if !ok && !had_menu {
+                sink.send(move |state| {
+                    state.open_root_menu(seat_name, id);
+                });
+            }
+        };
+        let activation = match secondary {
+            false => self.sni.activate(x, y, cb),
+            true => self.sni.secondary_activate(x, y, cb),
+        };
+        self.current_activation = Some(activation);
+    }
+
+    pub fn handle_activation_token(
+        &mut self,
+        s: &Singletons,
+        token: &XdgActivationTokenV1,
+        value: &str,
+    ) {
+        let Some(pending) = self.pending_activation.take_if(|p| &p.token == token) else {
+            return;
+        };
+        self.sni.provide_xdg_activation_token(value);
+        self.activate(
+            s,
+            pending.seat_name,
+            pending.secondary,
+            pending.x,
+            pending.y,
+            pending.had_menu,
+        );
+    }
+
+    pub fn handle_key(
+        &mut self,
+        seat: &Seat,
+        menu: Option<MenuId>,
+        s: &Singletons,
+        item: &Item,
+        key: MenuKey,
+    ) {
+        if menu.is_none() {
+            return;
+        }
+        let Some(instance) = &mut self.menu else {
+            return;
+        };
+        match instance.handle_key(seat, &item.menu, s, key) {
+            MenuKeyResult::None => {}
+            MenuKeyResult::Activated => {
+                if !settings::get().keep_open {
+                    self.menu = None;
+                }
+            }
+            MenuKeyResult::Close => self.menu = None,
+        }
+    }
+
+    /// Returns the position of the seat's pointer on the item.
+    ///
+    /// The positions of surfaces on the screen are unknown, so this is only an
+    /// approximation of what the protocol expects.
+    fn position(&self, seat: &Seat) -> (i32, i32) {
+        self.seat_positions
+            .get(&seat.name())
+            .copied()
+            .unwrap_or_default()
+    }
+
     pub fn open_root_menu(&mut self, seat: &Seat, s: &Singletons, item: &Item) {
+        if !self.sni.has_menu() {
+            let (x, y) = self.position(seat);
+            self.sni.context_menu(x, y);
+            return;
+        }
         let Some(&serial) = self.seat_serials.get(&seat.name()) else {
             return;
         };
@@ -282,62 +481,85 @@ impl TrayItem {
             if self.menu.is_some() {
                 return;
             }
-            let Some(&serial) = self.seat_serials.get(&seat.name()) else {
-                return;
-            };
-            let Some((x, y)) = self.seat_positions.get(&seat.name()).copied() else {
-                return;
-            };
             let Some(item) = items.items.get(&self.id.item) else {
                 return;
             };
-            let title = 'title: {
-                if let Some(tooltip) = &item.props.tooltip {
-                    if tooltip.title.is_not_empty() {
-                        break 'title &*tooltip.title;
-                    }
-                }
-                if let Some(title) = &item.props.title {
-                    if title.is_not_empty() {
-                        break 'title title;
-                    }
-                };
-                return;
-            };
-            let id = PopupId {
-                tray_item: self.id,
-                ty: PopupIdType::Tooltip,
-            };
-            let tooltip = match create_tooltip(s, self.scale, title) {
-                Ok(t) => t,
-                Err(e) => {
-                    log::error!("Could not create tooltip: {}", Report::new(e));
-                    return;
-                }
-            };
-            let positioner = s.xdg_wm_base.create_positioner(&s.qh, ());
-            positioner.set_size(tooltip.log_size.0, tooltip.log_size.1);
-            positioner.set_anchor_rect(x, y, 1, 1);
-            positioner.set_anchor(Anchor::BottomLeft);
-            positioner.set_gravity(Gravity::BottomLeft);
-            positioner.set_offset(-2, 2);
-            positioner.set_constraint_adjustment(ConstraintAdjustment::all());
-            let xdg = s.xdg_wm_base.get_xdg_surface(&tooltip.surface, &s.qh, id);
-            let popup = xdg.get_popup(None, &positioner, &s.qh, id);
-            positioner.destroy();
-            self.item.get_popup(&popup, seat.wl_seat(), serial);
-            tooltip.surface.commit();
-            self.tooltip = Some(TrayItemPopup {
-                tooltip,
-                xdg_surface: xdg,
-                xdg_popup: popup,
-            });
+            self.open_tooltip(s, item, seat.wl_seat(), seat.name());
         }
     }
 
-    pub fn handle_scroll(&mut self, menu: Option<MenuId>, axis: Axis, steps: i32) {
-        if menu.is_none() {
+    fn open_tooltip(&mut self, s: &Singletons, item: &Item, wl_seat: &WlSeat, seat_name: u32) {
+        let Some(&serial) = self.seat_serials.get(&seat_name) else {
+            return;
+        };
+        let Some((x, y)) = self.seat_positions.get(&seat_name).copied() else {
+            return;
+        };
+        let tooltip = item.props.tooltip.as_deref();
+        let mut title = tooltip.map(|t| &*t.title).unwrap_or_default();
+        if title.is_empty() {
+            title = item.title().map(|t| &***t).unwrap_or_default();
+        }
+        let text = tooltip.map(|t| &*t.text).unwrap_or_default();
+        if title.is_empty() && text.is_empty() {
+            return;
+        }
+        let content = TooltipContent {
+            title,
+            text,
+            icon: &item.tooltip_icon,
+        };
+        let id = PopupId {
+            tray_item: self.id,
+            ty: PopupIdType::Tooltip,
+        };
+        let tooltip = match create_tooltip(s, self.scale, &content) {
+            Ok(t) => t,
+            Err(e) => {
+                log::error!("Could not create tooltip: {}", Report::new(e));
+                return;
+            }
+        };
+        let positioner = s.xdg_wm_base.create_positioner(&s.qh, ());
+        positioner.set_size(tooltip.log_size.0, tooltip.log_size.1);
+        let (ox, oy) = self.item.popup_origin();
+        positioner.set_anchor_rect(ox + x, oy + y, 1, 1);
+        positioner.set_anchor(Anchor::BottomLeft);
+        positioner.set_gravity(Gravity::BottomLeft);
+        positioner.set_offset(-2, 2);
+        positioner.set_constraint_adjustment(ConstraintAdjustment::all());
+        let xdg = s.xdg_wm_base.get_xdg_surface(&tooltip.surface, &s.qh, id);
+        let popup = xdg.get_popup(None, &positioner, &s.qh, id);
+        positioner.destroy();
+        self.item.get_popup(&popup, wl_seat, serial, false);
+        tooltip.surface.commit();
+        self.tooltip = Some(TrayItemPopup {
+            tooltip,
+            wl_seat: wl_seat.clone(),
+            seat_name,
+            xdg_surface: xdg,
+            xdg_popup: popup,
+        });
+    }
+
+    pub fn handle_scroll(
+        &mut self,
+        seat: &Seat,
+        menu: Option<MenuId>,
+        s: &Singletons,
+        item: &Item,
+        axis: Axis,
+        steps: i32,
+    ) {
+        let Some(menu_id) = menu else {
             self.sni.scroll(steps, axis);
+            return;
+        };
+        if axis != Axis::VerticalScroll {
+            return;
+        }
+        if let Some(menu) = &mut self.menu {
+            menu.handle_scroll(seat, &item.menu, s, menu_id, steps);
         }
     }
 
@@ -373,11 +595,45 @@ impl TrayItem {
 
     pub fn handle_item_prop_changed(&mut self, s: &Singletons, item: &Item, prop: MutableProperty) {
         match prop {
-            MutableProperty::Title => self.tooltip = None,
-            MutableProperty::Icon | MutableProperty::AttentionIcon | MutableProperty::Status => {
+            MutableProperty::ToolTip => {
+                self.reopen_tooltip(s, item);
+            }
+            MutableProperty::Title => {
+                self.reopen_tooltip(s, item);
+                self.configure(None, s, item);
+            }
+            MutableProperty::Icon
+            | MutableProperty::AttentionIcon
+            | MutableProperty::OverlayIcon
+            | MutableProperty::Status
+            | MutableProperty::Id
+            | MutableProperty::Label => {
+                self.configure(None, s, item);
+            }
+            MutableProperty::IconThemePath => {
+                self.reopen_tooltip(s, item);
                 self.configure(None, s, item);
             }
-            _ => {}
+            MutableProperty::Category | MutableProperty::ItemIsMenu => {}
+        }
+    }
+
+    /// Re-renders the icon, the menu, and the tooltip with the current settings.
+    pub fn handle_settings_changed(&mut self, s: &Singletons, item: &Item) {
+        if let Some(menu) = &mut self.menu {
+            if !menu.handle_settings_changed(&item.menu, s) {
+                self.menu = None;
+            }
+        }
+        self.reopen_tooltip(s, item);
+        self.configure(None, s, item);
+    }
+
+    fn reopen_tooltip(&mut self, s: &Singletons, item: &Item) {
+        if let Some(tt) = self.tooltip.take() {
+            let (wl_seat, seat_name) = (tt.wl_seat.clone(), tt.seat_name);
+            drop(tt);
+            self.open_tooltip(s, item, &wl_seat, seat_name);
         }
     }
 
@@ -401,6 +657,21 @@ impl TrayItem {
         }
     }
 
+    pub fn handle_popup_geometry(
+        &mut self,
+        s: &Singletons,
+        item: &Item,
+        ty: PopupIdType,
+        height: i32,
+    ) {
+        let PopupIdType::MenuId(id) = ty else {
+            return;
+        };
+        if let Some(menu) = &mut self.menu {
+            menu.handle_geometry(&item.menu, s, id, height);
+        }
+    }
+
     pub fn handle_popup_done(&mut self, ty: PopupIdType) {
         match ty {
             PopupIdType::Tooltip => {
@@ -0,0 +1,75 @@
+use {
+    crate::wayland::{tray::TrayItemId, Singletons},
+    std::time::Duration,
+    tokio::task::JoinHandle,
+    wayland_client::protocol::{wl_callback::WlCallback, wl_surface::WlSurface},
+};
+
+/// The animation state of a tray item.
+///
+/// After a frame has been committed, we wait for the compositor to send the frame
+/// callback before starting the timer for the next frame. Surfaces that are not visible
+/// therefore do not cause any work.
+#[derive(Default)]
+pub struct Animation {
+    pub frame: usize,
+    delay: Duration,
+    frame_callback: Option<WlCallback>,
+    timer: Option<Timer>,
+}
+
+struct Timer {
+    future: JoinHandle<()>,
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        self.future.abort();
+    }
+}
+
+impl Animation {
+    pub fn stop(&mut self) {
+        *self = Default::default();
+    }
+
+    /// Must be called before committing the current frame.
+    pub fn schedule(
+        &mut self,
+        s: &Singletons,
+        surface: &WlSurface,
+        id: TrayItemId,
+        delay: Duration,
+    ) {
+        if self.frame_callback.is_some() || self.timer.is_some() {
+            return;
+        }
+        self.delay = delay;
+        self.frame_callback = Some(surface.frame(&s.qh, id));
+    }
+
+    pub fn handle_frame_done(&mut self, s: &Singletons, callback: &WlCallback, id: TrayItemId) {
+        if self.frame_callback.as_ref() != Some(callback) {
+            return;
+        }
+        self.frame_callback = None;
+        let sink = s.sink.clone();
+        let delay = self.delay;
+        let future = tokio::task::spawn(async move {
+            tokio::time::sleep(delay).await;
+            sink.send(move |state| {
+                state.handle_animation_tick(id);
+            });
+        });
+        self.timer = Some(Timer { future });
+    }
+
+    /// Returns whether the animation advanced to the next frame.
+    pub fn handle_tick(&mut self) -> bool {
+        if self.timer.take().is_none() {
+            return false;
+        }
+        self.frame = self.frame.wrapping_add(1);
+        true
+    }
+}
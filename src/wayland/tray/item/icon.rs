@@ -2,12 +2,23 @@ use {
     crate::{
         settings::ThemeColor,
         sni::IconFrames,
-        wayland::{utils::create_shm_buf_oneshot, Singletons},
+        wayland::{
+            tray::item::{
+                icon::{index::ThemeIndex, lru::Lru},
+                label::{Label, LabelError},
+            },
+            utils::create_shm_buf_oneshot,
+            Singletons,
+        },
     },
     ahash::{AHashMap, AHashSet},
     error_reporter::Report,
     ini::{Ini, ParseError},
-    pangocairo::cairo::{self},
+    pangocairo::{
+        cairo::{self},
+        pango::{self},
+    },
+    parking_lot::Mutex,
     png::Transformations,
     resvg::{
         tiny_skia::{PixmapMut, Transform},
@@ -23,20 +34,46 @@ use {
             atomic::{AtomicUsize, Ordering::Relaxed},
             Arc, LazyLock,
         },
+        time::{Duration, Instant, SystemTime},
     },
     thiserror::Error,
     wayland_client::protocol::wl_buffer::WlBuffer,
 };
 
+mod index;
+mod lru;
+
 static VERSION: AtomicUsize = AtomicUsize::new(1);
 
+/// The number of icon lookups whose results are cached.
+const LOOKUP_CACHE_SIZE: usize = 1024;
+/// The number of item theme paths whose themes are cached.
+const THEME_PATH_CACHE_SIZE: usize = 64;
+/// How long theme indices and lookup results are used before they are checked again.
+/// This matches GTK.
+const CACHE_TTL: Duration = Duration::from_secs(5);
+
+/// The name, size, scale, and theme of an icon lookup.
+type LookupKey = (String, i32, i32, String);
+
+/// The result of an icon lookup and when it was made.
+type LookupResult = (Option<PathBuf>, Instant);
+
+/// The results of icon lookups in the system themes.
+static LOOKUPS: LazyLock<Mutex<Lru<LookupKey, LookupResult>>> =
+    LazyLock::new(|| Mutex::new(Lru::new(LOOKUP_CACHE_SIZE)));
+
 #[derive(Default)]
 pub struct IconTemplate {
     version: usize,
     name: Option<Arc<String>>,
     frames: Option<IconFrames>,
     path: Option<Arc<String>>,
-    themes: AHashMap<String, Vec<Theme>>,
+    themes: Arc<Themes>,
+    /// The icon of the item's desktop entry.
+    fallback_name: Option<Arc<String>>,
+    /// The text whose first letter is drawn if no icon can be found.
+    avatar: Option<Arc<String>>,
 }
 
 #[derive(Default)]
@@ -44,6 +81,7 @@ struct IconVersion {
     version: usize,
     size: (i32, i32),
     scale: i32,
+    theme: String,
     color: ThemeColor,
 }
 
@@ -54,6 +92,8 @@ pub struct BufferIconFrame {
 #[derive(Default)]
 pub struct BufferIcon {
     version: IconVersion,
+    overlay_version: IconVersion,
+    label: Option<Label>,
     buffer: Option<(BufferIconFrame, (i32, i32))>,
 }
 
@@ -88,13 +128,21 @@ impl IconTemplate {
         self.name = name.cloned();
         if self.path.as_ref() != path {
             self.path = path.cloned();
-            self.themes.clear();
-            if let Some(path) = path {
-                parse_themes_in_dir(Path::new(&**path), &mut self.themes);
-            }
+            self.themes = path.map(|p| themes_in_path(p)).unwrap_or_default();
         }
     }
 
+    /// Sets the fallbacks that are used if neither the name nor the frames can be
+    /// realized.
+    pub fn update_fallback(&mut self, name: Option<&Arc<String>>, avatar: Option<&Arc<String>>) {
+        if (self.fallback_name.as_ref(), self.avatar.as_ref()) == (name, avatar) {
+            return;
+        }
+        self.version = VERSION.fetch_add(1, Relaxed);
+        self.fallback_name = name.cloned();
+        self.avatar = avatar.cloned();
+    }
+
     pub fn update_frames(&mut self, mut frames: Option<&IconFrames>) {
         if let Some(f) = frames {
             if f.frames.is_empty() {
@@ -114,6 +162,41 @@ impl IconTemplate {
         scale: i32,
         theme: &str,
         color: &ThemeColor,
+    ) -> Option<(Vec<u8>, (i32, i32))> {
+        if let Some(res) = self.try_realize(size, scale, theme, color) {
+            return Some(res);
+        }
+        if let Some(name) = &self.fallback_name {
+            if let Some(res) = name_to_bytes(name, size, scale, theme, None, color) {
+                return Some(res);
+            }
+        }
+        if let Some(text) = &self.avatar {
+            match render_avatar(text, size) {
+                Ok(Some(data)) => return Some((data, size)),
+                Ok(None) => {}
+                Err(e) => log::error!("Could not render avatar: {}", Report::new(e)),
+            }
+        }
+        if self.name.is_none() && self.frames.is_none() {
+            return None;
+        }
+        let data = match render_svg(include_bytes!("fallback.svg"), size, color) {
+            Ok(d) => d,
+            Err(e) => {
+                log::error!("Could not render fallback: {}", Report::new(e));
+                vec![255; (size.0 * size.1 * 4) as usize]
+            }
+        };
+        Some((data, size))
+    }
+
+    fn try_realize(
+        &self,
+        size: (i32, i32),
+        scale: i32,
+        theme: &str,
+        color: &ThemeColor,
     ) -> Option<(Vec<u8>, (i32, i32))> {
         if let Some(name) = &self.name {
             let custom_themes = self.path.as_ref().map(|dir| CustomThemes {
@@ -149,17 +232,7 @@ impl IconTemplate {
             }
             return Some((bytes, frame.size));
         }
-        if self.name.is_none() && self.frames.is_none() {
-            return None;
-        }
-        let data = match render_svg(include_bytes!("fallback.svg"), size, color) {
-            Ok(d) => d,
-            Err(e) => {
-                log::error!("Could not render fallback: {}", Report::new(e));
-                vec![255; (size.0 * size.1) as usize]
-            }
-        };
-        Some((data, size))
+        None
     }
 }
 
@@ -169,9 +242,10 @@ impl IconVersion {
         template: &IconTemplate,
         size: (i32, i32),
         scale: i32,
+        theme: &str,
         color: &ThemeColor,
     ) -> bool {
-        if self.version == template.version {
+        if self.version == template.version && self.theme == theme {
             if template.frames.is_some() {
                 return true;
             }
@@ -182,6 +256,7 @@ impl IconVersion {
         self.version = template.version;
         self.size = size;
         self.scale = scale;
+        self.theme = theme.to_string();
         self.color = *color;
         false
     }
@@ -191,6 +266,18 @@ impl IconVersion {
 enum BufferIconError {
     #[error("Could not create memfd")]
     CreateShmBuffer(#[source] io::Error),
+    #[error("Could not draw the overlay icon")]
+    Overlay(#[source] DrawError),
+    #[error("Could not draw the label")]
+    Label(#[source] LabelError),
+}
+
+#[derive(Debug, Error)]
+enum DrawError {
+    #[error(transparent)]
+    Cairo(#[from] cairo::Error),
+    #[error(transparent)]
+    Borrow(#[from] cairo::BorrowError),
 }
 
 impl BufferIcon {
@@ -198,35 +285,75 @@ impl BufferIcon {
         self.buffer.as_ref()
     }
 
+    #[expect(clippy::too_many_arguments)]
     pub fn update(
         &mut self,
         template: &IconTemplate,
+        overlay: &IconTemplate,
+        label: Option<&Label>,
         size: (i32, i32),
         scale: i32,
         theme: &str,
         color: &ThemeColor,
         s: &Singletons,
     ) {
-        if let Err(e) = self.try_update(template, size, scale, theme, color, s) {
+        let res = self.try_update(template, overlay, label, size, scale, theme, color, s);
+        if let Err(e) = res {
             log::error!("Could not update buffers: {}", Report::new(e));
         }
     }
 
+    #[expect(clippy::too_many_arguments)]
     fn try_update(
         &mut self,
         template: &IconTemplate,
+        overlay: &IconTemplate,
+        label: Option<&Label>,
         size: (i32, i32),
         scale: i32,
         theme: &str,
         color: &ThemeColor,
         s: &Singletons,
     ) -> Result<(), BufferIconError> {
-        if self.version.update(template, size, scale, color) {
+        let template_current = self.version.update(template, size, scale, theme, color);
+        let overlay_current = self
+            .overlay_version
+            .update(overlay, size, scale, theme, color);
+        let label_current = self.label.as_ref() == label;
+        if template_current && overlay_current && label_current {
             return Ok(());
         }
+        self.label = label.cloned();
         self.buffer.take();
-        let Some((contents, size)) = template.realize(size, scale, theme, color) else {
-            return Ok(());
+        let icon_size = match label {
+            Some(label) => label.icon_size(size),
+            None => Some(size),
+        };
+        let mut icon = None;
+        if let Some(icon_size) = icon_size {
+            icon = template.realize(icon_size, scale, theme, color);
+        }
+        if let Some((contents, size)) = &mut icon {
+            if overlay.is_some() {
+                let size = *size;
+                let overlay_size = ((size.0 + 1) / 2, (size.1 + 1) / 2);
+                if let Some(o) = overlay.try_realize(overlay_size, scale, theme, color) {
+                    *contents = draw_overlay(contents, size, &o.0, o.1, overlay_size)
+                        .map_err(BufferIconError::Overlay)?;
+                }
+            }
+        }
+        let (contents, size) = match label {
+            Some(label) => {
+                let contents = label
+                    .draw(icon.as_ref(), size)
+                    .map_err(BufferIconError::Label)?;
+                (contents, size)
+            }
+            None => match icon {
+                Some(icon) => icon,
+                None => return Ok(()),
+            },
         };
         let buffer =
             create_shm_buf_oneshot(s, &contents, size).map_err(BufferIconError::CreateShmBuffer)?;
@@ -248,7 +375,7 @@ impl CairoIcon {
         theme: &str,
         color: &ThemeColor,
     ) {
-        if self.version.update(template, size, scale, color) {
+        if self.version.update(template, size, scale, theme, color) {
             return;
         }
         self.surface.take();
@@ -279,6 +406,46 @@ impl CairoIcon {
     }
 }
 
+/// Draws the overlay into the bottom-right corner of the icon.
+///
+/// Both inputs and the output are premultiplied BGRA.
+fn draw_overlay(
+    icon: &[u8],
+    icon_size: (i32, i32),
+    overlay: &[u8],
+    overlay_size: (i32, i32),
+    target_size: (i32, i32),
+) -> Result<Vec<u8>, DrawError> {
+    let image_surface = |bytes: &[u8], size: (i32, i32)| {
+        let mut surface = cairo::ImageSurface::create(cairo::Format::ARgb32, size.0, size.1)?;
+        {
+            let mut data = surface.data()?;
+            let len = data.len();
+            data.copy_from_slice(&bytes[..len]);
+        }
+        surface.mark_dirty();
+        Ok::<_, DrawError>(surface)
+    };
+    let mut surface = image_surface(icon, icon_size)?;
+    let overlay = image_surface(overlay, overlay_size)?;
+    {
+        let cairo = cairo::Context::new(&surface)?;
+        cairo.translate(
+            (icon_size.0 - target_size.0) as f64,
+            (icon_size.1 - target_size.1) as f64,
+        );
+        cairo.scale(
+            target_size.0 as f64 / overlay_size.0 as f64,
+            target_size.1 as f64 / overlay_size.1 as f64,
+        );
+        cairo.set_source_surface(&overlay, 0.0, 0.0)?;
+        cairo.paint()?;
+    }
+    surface.flush();
+    let data = surface.data()?.to_vec();
+    Ok(data)
+}
+
 fn name_to_bytes(
     name: &str,
     size: (i32, i32),
@@ -328,6 +495,61 @@ fn name_to_bytes(
     Some((contents, size))
 }
 
+/// Draws the first letter of `text` onto a circle whose color is derived from `text`.
+///
+/// Returns `None` if `text` contains no letters or digits. The output is premultiplied
+/// BGRA.
+fn render_avatar(text: &str, size: (i32, i32)) -> Result<Option<Vec<u8>>, DrawError> {
+    let Some(letter) = text.chars().find(|c| c.is_alphanumeric()) else {
+        return Ok(None);
+    };
+    let letter: String = letter.to_uppercase().collect();
+    let hue = text
+        .bytes()
+        .fold(0u32, |h, b| h.wrapping_mul(31).wrapping_add(b as u32))
+        % 360;
+    let (r, g, b) = hsl_to_rgb(hue as f64, 0.5, 0.4);
+    let mut surface = cairo::ImageSurface::create(cairo::Format::ARgb32, size.0, size.1)?;
+    {
+        let cairo = cairo::Context::new(&surface)?;
+        let diameter = size.0.min(size.1) as f64;
+        let (cx, cy) = (size.0 as f64 / 2.0, size.1 as f64 / 2.0);
+        cairo.arc(cx, cy, diameter / 2.0, 0.0, std::f64::consts::TAU);
+        cairo.set_source_rgb(r, g, b);
+        cairo.fill()?;
+        let layout = pangocairo::functions::create_layout(&cairo);
+        let mut font = pango::FontDescription::from_string("sans bold");
+        font.set_absolute_size(diameter * 0.6 * pango::SCALE as f64);
+        layout.set_font_description(Some(&font));
+        layout.set_text(&letter);
+        let (ink, _) = layout.pixel_extents();
+        cairo.move_to(
+            cx - ink.x() as f64 - ink.width() as f64 / 2.0,
+            cy - ink.y() as f64 - ink.height() as f64 / 2.0,
+        );
+        cairo.set_source_rgb(1.0, 1.0, 1.0);
+        pangocairo::functions::show_layout(&cairo, &layout);
+    }
+    surface.flush();
+    let data = surface.data()?.to_vec();
+    Ok(Some(data))
+}
+
+fn hsl_to_rgb(h: f64, s: f64, l: f64) -> (f64, f64, f64) {
+    let c = (1.0 - (2.0 * l - 1.0).abs()) * s;
+    let x = c * (1.0 - ((h / 60.0) % 2.0 - 1.0).abs());
+    let (r, g, b) = match h as u32 / 60 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+    let m = l - c / 2.0;
+    (r + m, g + m, b + m)
+}
+
 fn render_svg(
     contents: &[u8],
     size: (i32, i32),
@@ -375,6 +597,22 @@ struct CustomThemes<'a> {
     themes: &'a AHashMap<String, Vec<Theme>>,
 }
 
+/// Looks up the path of an icon, taking the themes in `theme_path` into account.
+pub fn find_icon_file(
+    name: &str,
+    size: i32,
+    scale: i32,
+    theme: &str,
+    theme_path: Option<&str>,
+) -> Option<PathBuf> {
+    let themes = theme_path.map(themes_in_path).unwrap_or_default();
+    let custom_themes = theme_path.map(|dir| CustomThemes {
+        dir,
+        themes: &themes,
+    });
+    find_icon(name, size, scale, theme, custom_themes).map(|l| l.path)
+}
+
 fn find_icon(
     name: &str,
     size: i32,
@@ -391,6 +629,9 @@ fn find_icon(
             }
         }
     }
+    // Applications might add icons to their theme path at any time, so these lookups
+    // are not cached. The theme indices notice such changes through the directory
+    // modification times.
     if let Some(custom) = custom_themes {
         let dir = Path::new(custom.dir);
         let res = find_icon_within([dir], custom.themes, name, size, scale, theme);
@@ -398,14 +639,34 @@ fn find_icon(
             return res;
         }
     }
-    find_icon_within(
+    let key = (name.to_string(), size, scale, theme.to_string());
+    let now = Instant::now();
+    if let Some((path, time)) = LOOKUPS.lock().get(&key) {
+        if now - *time < CACHE_TTL {
+            return path.clone().map(|path| IconLookup { path });
+        }
+    }
+    let res = find_icon_within(
         BASE_DIRS.iter().map(|d| &**d),
-        &THEMES,
+        &themes(),
         name,
         size,
         scale,
         theme,
-    )
+    );
+    LOOKUPS
+        .lock()
+        .insert(key, (res.as_ref().map(|l| l.path.clone()), now));
+    res
+}
+
+/// Discards the system themes and all cached lookups.
+///
+/// The themes are parsed again on the next lookup.
+pub fn clear_caches() {
+    *THEMES.lock() = None;
+    THEME_PATHS.lock().clear();
+    LOOKUPS.lock().clear();
 }
 
 fn find_icon_within<'a, I>(
@@ -462,30 +723,32 @@ fn find_icon_helper<'a>(
 }
 
 fn lookup_icon(name: &str, size: i32, scale: i32, theme: &Theme) -> Option<IconLookup> {
-    for dir in &theme.directories {
-        if let Some(variant) = theme.variants.get(dir) {
+    let index = theme.index();
+    let icons = index.get(name);
+    let lookup = |&(idx, ext): &(usize, &str)| IconLookup {
+        path: theme
+            .dir
+            .join(format!("./{}/{name}.{ext}", theme.directories[idx])),
+    };
+    for icon in icons {
+        if let Some(variant) = theme.variants.get(&theme.directories[icon.0]) {
             if variant.permits_size(size, scale) {
-                if let Some(path) = find_icon_in_dir(&theme.dir, dir, name) {
-                    return Some(IconLookup { path });
-                }
+                return Some(lookup(icon));
             }
         }
     }
     let mut min_size = i32::MAX;
     let mut closest = None;
-    for dir in &theme.directories {
-        if let Some(variant) = theme.variants.get(dir) {
+    for icon in icons {
+        if let Some(variant) = theme.variants.get(&theme.directories[icon.0]) {
             let dist = variant.distance(size, scale);
-            if dist >= min_size {
-                continue;
-            }
-            if let Some(path) = find_icon_in_dir(&theme.dir, dir, name) {
+            if dist < min_size {
                 min_size = dist;
-                closest = Some(IconLookup { path });
+                closest = Some(icon);
             }
         }
     }
-    closest
+    closest.map(lookup)
 }
 
 fn find_icon_in_dir(dir: &Path, subdir: &str, name: &str) -> Option<PathBuf> {
@@ -538,13 +801,48 @@ impl Variant {
     }
 }
 
-static THEMES: LazyLock<AHashMap<String, Vec<Theme>>> = LazyLock::new(|| {
-    let mut themes = AHashMap::<_, Vec<_>>::new();
-    for dir in &*BASE_DIRS {
-        parse_themes_in_dir(dir, &mut themes);
+type Themes = AHashMap<String, Vec<Theme>>;
+
+static THEMES: Mutex<Option<Arc<Themes>>> = Mutex::new(None);
+
+/// The themes in a theme path and the modification time of the path.
+type PathThemes = (Arc<Themes>, Option<SystemTime>);
+
+/// The themes in the theme paths of items.
+static THEME_PATHS: LazyLock<Mutex<Lru<String, PathThemes>>> =
+    LazyLock::new(|| Mutex::new(Lru::new(THEME_PATH_CACHE_SIZE)));
+
+/// Returns the themes in the base directories.
+fn themes() -> Arc<Themes> {
+    THEMES
+        .lock()
+        .get_or_insert_with(|| {
+            let mut themes = AHashMap::<_, Vec<_>>::new();
+            for dir in &*BASE_DIRS {
+                parse_themes_in_dir(dir, &mut themes);
+            }
+            Arc::new(themes)
+        })
+        .clone()
+}
+
+/// Returns the themes in the theme path of an item.
+///
+/// The themes are parsed again if themes have been added to or removed from the path.
+fn themes_in_path(dir: &str) -> Arc<Themes> {
+    let dir = dir.to_string();
+    let mtime = std::fs::metadata(&dir).and_then(|m| m.modified()).ok();
+    if let Some((themes, m)) = THEME_PATHS.lock().get(&dir) {
+        if *m == mtime {
+            return themes.clone();
+        }
     }
+    let mut themes = AHashMap::new();
+    parse_themes_in_dir(Path::new(&dir), &mut themes);
+    let themes = Arc::new(themes);
+    THEME_PATHS.lock().insert(dir, (themes.clone(), mtime));
     themes
-});
+}
 
 fn parse_themes_in_dir(dir: &Path, out: &mut AHashMap<String, Vec<Theme>>) {
     let Ok(mut dir) = dir.read_dir() else {
@@ -578,6 +876,29 @@ struct Theme {
     inherits: Vec<String>,
     directories: Vec<String>,
     variants: AHashMap<String, Variant>,
+    /// The index and when it was last checked to be up to date.
+    index: Mutex<Option<CachedIndex>>,
+}
+
+type CachedIndex = (Arc<ThemeIndex>, Instant);
+
+impl Theme {
+    fn index(&self) -> Arc<ThemeIndex> {
+        let mut cache = self.index.lock();
+        let now = Instant::now();
+        if let Some((index, checked)) = &mut *cache {
+            if now - *checked < CACHE_TTL {
+                return index.clone();
+            }
+            if !index.is_outdated(&self.dir, &self.directories) {
+                *checked = now;
+                return index.clone();
+            }
+        }
+        let index = Arc::new(ThemeIndex::new(&self.dir, &self.directories));
+        *cache = Some((index.clone(), now));
+        index
+    }
 }
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
@@ -629,6 +950,7 @@ fn parse_theme(dir: &Path) -> Result<Option<Theme>, ThemeError> {
             .chain(split("ScaledDirectories"))
             .collect(),
         variants: Default::default(),
+        index: Default::default(),
     };
     for (section, props) in ini.iter() {
         let Some(section) = section else {
@@ -0,0 +1,219 @@
+use {
+    ahash::AHashMap,
+    std::{fs, iter, path::Path, time::SystemTime},
+};
+
+const CACHE_FILE: &str = "icon-theme.cache";
+const HAS_SUFFIX_SVG: u16 = 2;
+const HAS_SUFFIX_PNG: u16 = 4;
+const NONE: usize = u32::MAX as usize;
+
+/// The icons of a theme.
+#[derive(Debug, Default)]
+pub struct ThemeIndex {
+    /// Maps icon names to the indices of the theme directories containing them and the
+    /// preferred file extension. Ordered by the directory index.
+    icons: AHashMap<String, Vec<(usize, &'static str)>>,
+    /// The modification times of the theme directory and its subdirectories.
+    mtimes: Vec<Option<SystemTime>>,
+}
+
+impl ThemeIndex {
+    /// Builds the index of the theme in `dir`.
+    ///
+    /// If the theme has an up-to-date `icon-theme.cache`, the index is built from the
+    /// cache. Otherwise the `directories` of the theme are scanned.
+    pub fn new(dir: &Path, directories: &[String]) -> Self {
+        let mtimes = mtimes(dir, directories);
+        if let Some(icons) = read_cache(dir, directories) {
+            return Self { icons, mtimes };
+        }
+        let mut icons = AHashMap::<_, Vec<_>>::new();
+        for (idx, subdir) in directories.iter().enumerate() {
+            let Ok(entries) = dir.join(subdir).read_dir() else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                let file_name = entry.file_name();
+                let Some(file_name) = file_name.to_str() else {
+                    continue;
+                };
+                let Some((name, ext)) = file_name.rsplit_once('.') else {
+                    continue;
+                };
+                let ext = match ext {
+                    "svg" => "svg",
+                    "png" => "png",
+                    _ => continue,
+                };
+                let entries = icons.entry(name.to_string()).or_default();
+                match entries.last_mut() {
+                    Some((i, e)) if *i == idx => {
+                        if ext == "svg" {
+                            *e = ext;
+                        }
+                    }
+                    _ => entries.push((idx, ext)),
+                }
+            }
+        }
+        Self { icons, mtimes }
+    }
+
+    /// Returns whether any of the directories has been modified since the index was
+    /// built.
+    pub fn is_outdated(&self, dir: &Path, directories: &[String]) -> bool {
+        self.mtimes != mtimes(dir, directories)
+    }
+
+    /// Returns the directories containing the icon and the file extension to use.
+    pub fn get(&self, name: &str) -> &[(usize, &'static str)] {
+        self.icons.get(name).map(|v| &**v).unwrap_or_default()
+    }
+}
+
+fn mtimes(dir: &Path, directories: &[String]) -> Vec<Option<SystemTime>> {
+    iter::once(dir.to_owned())
+        .chain(directories.iter().map(|d| dir.join(d)))
+        .map(|path| fs::metadata(path).and_then(|m| m.modified()).ok())
+        .collect()
+}
+
+fn read_cache(
+    dir: &Path,
+    directories: &[String],
+) -> Option<AHashMap<String, Vec<(usize, &'static str)>>> {
+    let path = dir.join(CACHE_FILE);
+    let cache_mtime = fs::metadata(&path).ok()?.modified().ok()?;
+    let dir_mtime = fs::metadata(dir).ok()?.modified().ok()?;
+    if cache_mtime < dir_mtime {
+        log::debug!("{} is outdated", path.display());
+        return None;
+    }
+    let data = fs::read(&path).ok()?;
+    let res = parse_cache(&data, directories);
+    if res.is_none() {
+        log::debug!("{} is invalid", path.display());
+    }
+    res
+}
+
+/// Parses GTK's `icon-theme.cache` format.
+///
+/// The file consists of a header, a list of directory names, and a hash table whose
+/// buckets are chains of icons. Each icon has a list of the directories that contain
+/// it. All numbers are big-endian and offsets are relative to the start of the file.
+fn parse_cache(
+    data: &[u8],
+    directories: &[String],
+) -> Option<AHashMap<String, Vec<(usize, &'static str)>>> {
+    let u16_at = |offset: usize| -> Option<u16> {
+        let bytes = data.get(offset..offset.checked_add(2)?)?;
+        Some(u16::from_be_bytes(bytes.try_into().ok()?))
+    };
+    let u32_at = |offset: usize| -> Option<usize> {
+        let bytes = data.get(offset..offset.checked_add(4)?)?;
+        Some(u32::from_be_bytes(bytes.try_into().ok()?) as usize)
+    };
+    let str_at = |offset: usize| -> Option<&str> {
+        let bytes = data.get(offset..)?;
+        let len = bytes.iter().position(|&b| b == 0)?;
+        std::str::from_utf8(&bytes[..len]).ok()
+    };
+    if u16_at(0)? != 1 {
+        return None;
+    }
+    let hash = u32_at(4)?;
+    let directory_list = u32_at(8)?;
+    let mut dirs = vec![];
+    for i in 0..u32_at(directory_list)? {
+        let name = str_at(u32_at(directory_list + 4 + 4 * i)?)?;
+        dirs.push(directories.iter().position(|d| d == name));
+    }
+    let mut icons = AHashMap::<_, Vec<_>>::new();
+    // Every icon occupies at least 12 bytes, which bounds the number of icons in
+    // malformed files with cyclic chains.
+    let mut remaining = data.len() / 12;
+    for bucket in 0..u32_at(hash)? {
+        let mut icon = u32_at(hash + 4 + 4 * bucket)?;
+        while icon != NONE {
+            remaining = remaining.checked_sub(1)?;
+            let name = str_at(u32_at(icon + 4)?)?;
+            let images = u32_at(icon + 8)?;
+            let entries = icons.entry(name.to_string()).or_default();
+            for i in 0..u32_at(images)? {
+                let image = images + 4 + 8 * i;
+                let dir = u16_at(image)? as usize;
+                let flags = u16_at(image + 2)?;
+                let ext = match flags {
+                    _ if flags & HAS_SUFFIX_SVG != 0 => "svg",
+                    _ if flags & HAS_SUFFIX_PNG != 0 => "png",
+                    _ => continue,
+                };
+                if let Some(&Some(idx)) = dirs.get(dir) {
+                    entries.push((idx, ext));
+                }
+            }
+            entries.sort_by_key(|e| e.0);
+            icon = u32_at(icon)?;
+        }
+    }
+    Some(icons)
+}
+
+#[test]
+fn cache() {
+    fn u16(v: &mut Vec<u8>, n: u16) {
+        v.extend_from_slice(&n.to_be_bytes());
+    }
+    fn u32(v: &mut Vec<u8>, n: u32) {
+        v.extend_from_slice(&n.to_be_bytes());
+    }
+    let mut data = vec![];
+    // header
+    u16(&mut data, 1);
+    u16(&mut data, 0);
+    u32(&mut data, 12);
+    u32(&mut data, 48);
+    // hash with one bucket containing two icons
+    u32(&mut data, 1);
+    u32(&mut data, 20);
+    // icon "a"
+    u32(&mut data, 32);
+    u32(&mut data, 66);
+    u32(&mut data, 70);
+    // icon "b"
+    u32(&mut data, NONE as u32);
+    u32(&mut data, 68);
+    u32(&mut data, 90);
+    u32(&mut data, 0);
+    // directory list
+    u32(&mut data, 2);
+    u32(&mut data, 60);
+    u32(&mut data, 63);
+    data.extend_from_slice(b"16\0");
+    data.extend_from_slice(b"32\0");
+    data.extend_from_slice(b"a\0");
+    data.extend_from_slice(b"b\0");
+    // images of "a"
+    u32(&mut data, 2);
+    u16(&mut data, 1);
+    u16(&mut data, HAS_SUFFIX_PNG);
+    u32(&mut data, 0);
+    u16(&mut data, 0);
+    u16(&mut data, HAS_SUFFIX_PNG | HAS_SUFFIX_SVG);
+    u32(&mut data, 0);
+    // images of "b"
+    u32(&mut data, 1);
+    u16(&mut data, 0);
+    u16(&mut data, 1);
+    u32(&mut data, 0);
+
+    let directories = ["32".to_string(), "16".to_string()];
+    let icons = parse_cache(&data, &directories).unwrap();
+    assert_eq!(icons["a"], [(0, "png"), (1, "svg")]);
+    assert!(icons["b"].is_empty());
+
+    data.truncate(80);
+    assert!(parse_cache(&data, &directories).is_none());
+}
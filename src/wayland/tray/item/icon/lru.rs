@@ -0,0 +1,56 @@
+use {ahash::RandomState, indexmap::IndexMap, std::hash::Hash};
+
+/// A map that evicts the least recently used entry once it is full.
+pub struct Lru<K, V> {
+    capacity: usize,
+    /// Ordered from the least to the most recently used entry.
+    map: IndexMap<K, V, RandomState>,
+}
+
+impl<K, V> Lru<K, V>
+where
+    K: Hash + Eq,
+{
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            map: IndexMap::with_capacity_and_hasher(capacity, RandomState::new()),
+        }
+    }
+
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        let idx = self.map.get_index_of(key)?;
+        let last = self.map.len() - 1;
+        self.map.move_index(idx, last);
+        self.map.get_index(last).map(|(_, v)| v)
+    }
+
+    pub fn insert(&mut self, key: K, value: V) {
+        if let Some(idx) = self.map.get_index_of(&key) {
+            self.map.shift_remove_index(idx);
+        } else if self.map.len() >= self.capacity {
+            self.map.shift_remove_index(0);
+        }
+        self.map.insert(key, value);
+    }
+
+    pub fn clear(&mut self) {
+        self.map.clear();
+    }
+}
+
+#[test]
+fn least_recently_used_is_evicted() {
+    let mut lru = Lru::new(2);
+    lru.insert(1, "a");
+    lru.insert(2, "b");
+    assert_eq!(lru.get(&1), Some(&"a"));
+    lru.insert(3, "c");
+    assert_eq!(lru.get(&2), None);
+    assert_eq!(lru.get(&1), Some(&"a"));
+    assert_eq!(lru.get(&3), Some(&"c"));
+    lru.insert(3, "d");
+    lru.insert(4, "e");
+    assert_eq!(lru.get(&1), None);
+    assert_eq!(lru.get(&3), Some(&"d"));
+}
@@ -0,0 +1,113 @@
+use {
+    crate::settings::{LabelMode, LabelSettings},
+    pangocairo::{
+        cairo::{self, Format},
+        pango::{self},
+        FontMap,
+    },
+    std::sync::Arc,
+    thiserror::Error,
+};
+
+/// The text label of an item as it is drawn into the item's buffer.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Label {
+    pub text: Arc<String>,
+    pub guide: Option<Arc<String>>,
+    /// The factor by which the font size is scaled.
+    pub scale: f64,
+    pub settings: LabelSettings,
+}
+
+#[derive(Debug, Error)]
+pub enum LabelError {
+    #[error(transparent)]
+    Cairo(#[from] cairo::Error),
+    #[error(transparent)]
+    Borrow(#[from] cairo::BorrowError),
+    #[error("The icon has {0} bytes but {1} bytes are required")]
+    IconSize(usize, usize),
+}
+
+impl Label {
+    /// Returns the size of the icon that is drawn next to the label or `None` if the
+    /// label replaces the icon.
+    pub fn icon_size(&self, size: (i32, i32)) -> Option<(i32, i32)> {
+        match self.settings.mode {
+            LabelMode::Beside if size.0 > size.1 => Some((size.1, size.1)),
+            _ => None,
+        }
+    }
+
+    /// Draws the label and the icon into a buffer of the given size.
+    ///
+    /// The icon must have been realized with the size returned by [Self::icon_size].
+    /// Both the icon and the output are premultiplied BGRA.
+    pub fn draw(
+        &self,
+        icon: Option<&(Vec<u8>, (i32, i32))>,
+        size: (i32, i32),
+    ) -> Result<Vec<u8>, LabelError> {
+        let mut surface = cairo::ImageSurface::create(Format::ARgb32, size.0, size.1)?;
+        let mut text_x = 0.0;
+        {
+            let cairo = cairo::Context::new(&surface)?;
+            if let (Some((bytes, icon_size)), Some(target)) = (icon, self.icon_size(size)) {
+                let mut icon =
+                    cairo::ImageSurface::create(Format::ARgb32, icon_size.0, icon_size.1)?;
+                {
+                    let mut data = icon.data()?;
+                    let len = data.len();
+                    let Some(bytes) = bytes.get(..len) else {
+                        return Err(LabelError::IconSize(bytes.len(), len));
+                    };
+                    data.copy_from_slice(bytes);
+                }
+                icon.mark_dirty();
+                cairo.scale(
+                    target.0 as f64 / icon_size.0 as f64,
+                    target.1 as f64 / icon_size.1 as f64,
+                );
+                cairo.set_source_surface(&icon, 0.0, 0.0)?;
+                cairo.paint()?;
+                cairo.identity_matrix();
+                text_x = target.0 as f64;
+            }
+            let width = size.0 as f64 - text_x;
+            let height = size.1 as f64;
+            let ctx = pango::Context::new();
+            ctx.set_font_map(Some(&FontMap::default()));
+            let layout = pango::Layout::new(&ctx);
+            let mut font = self.settings.font.clone();
+            let font_size = font.size() as f64 * self.scale;
+            font.set_size(font_size.round() as _);
+            layout.set_font_description(Some(&font));
+            // The guide is measured so that the font size does not change with the text.
+            let mut text_size = (0, 0);
+            for text in [Some(&self.text), self.guide.as_ref()]
+                .into_iter()
+                .flatten()
+            {
+                layout.set_text(text);
+                let (w, h) = layout.pixel_size();
+                text_size = (text_size.0.max(w), text_size.1.max(h));
+            }
+            let fit = (width / text_size.0.max(1) as f64).min(height / text_size.1.max(1) as f64);
+            if fit < 1.0 {
+                font.set_size((font_size * fit).floor().max(1.0) as _);
+                layout.set_font_description(Some(&font));
+            }
+            layout.set_text(&self.text);
+            let (w, h) = layout.pixel_size();
+            self.settings.color.set(&cairo);
+            cairo.move_to(
+                text_x + ((width - w as f64) / 2.0).max(0.0),
+                ((height - h as f64) / 2.0).max(0.0),
+            );
+            pangocairo::functions::show_layout(&cairo, &layout);
+        }
+        surface.flush();
+        let data = surface.data()?.to_vec();
+        Ok(data)
+    }
+}
@@ -1,7 +1,10 @@
 use {
     crate::{
-        settings::{self},
-        sni::{IconFrame, IconFrames, SniItem, SniMenuDelta, SniMenuToggleType},
+        settings::{self, ThemeColor},
+        sni::{
+            IconFrame, IconFrames, SniItem, SniMenuAccessKey, SniMenuDelta, SniMenuDisposition,
+            SniMenuToggleState, SniMenuToggleType,
+        },
         wayland::{
             item::Items,
             scale::{Logical, Physical, Scale},
@@ -60,6 +63,37 @@ pub enum MenuError {
 
 pub type MenuId = i32;
 
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum MenuKey {
+    Up,
+    Down,
+    Home,
+    End,
+    Left,
+    Right,
+    Activate,
+    Escape,
+    Char(char),
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum MenuKeyResult {
+    None,
+    Activated,
+    Close,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum KeyAction {
+    None,
+    /// Close this menu and return to the parent.
+    Back,
+    /// Close the whole menu.
+    Close,
+    Activate(MenuId),
+    OpenChild(MenuId),
+}
+
 #[derive(Default)]
 pub struct Menu {
     items: AHashMap<MenuId, MenuItem>,
@@ -72,6 +106,9 @@ pub struct MenuInstance {
     icon_cache: AHashMap<MenuId, CairoIcon>,
     open_call: Option<Call>,
     open: OpenMenu,
+    /// The seat and menu for which the first item should be selected once the menu is
+    /// opened.
+    keyboard_select: Option<(u32, MenuId)>,
 }
 
 #[derive(Default)]
@@ -84,16 +121,21 @@ struct MenuItem {
     id: MenuId,
     separator: bool,
     label: Option<Arc<String>>,
+    access_key: Option<SniMenuAccessKey>,
     enabled: bool,
     visible: bool,
     icon_template: IconTemplate,
     toggle_type: Option<SniMenuToggleType>,
-    toggle_active: bool,
+    toggle_state: SniMenuToggleState,
+    shortcut: Option<String>,
+    disposition: SniMenuDisposition,
     submenu: Option<SubMenu>,
 }
 
 pub struct OpenMenu {
     id: MenuId,
+    sni: Arc<SniItem>,
+    closed_sent: bool,
     tray_item: TrayItemId,
     log_size: Logical,
     phy_size: Physical,
@@ -114,6 +156,15 @@ pub struct OpenMenu {
     seat_hover: AHashMap<u32, MenuId>,
     positioner: XdgPositioner,
     can_reposition: bool,
+    /// The size of the menu if it is not scrolled. Used for positioning.
+    content_log_size: Logical,
+    /// The rows of the menu in the coordinate space of the unscrolled menu.
+    content_rows: Vec<OpenMenuRow>,
+    /// The height of the popup as constrained by the compositor.
+    max_height: Option<i32>,
+    scroll: i32,
+    /// The height of the scroll arrows or 0 if the menu is not scrollable.
+    arrow_height: i32,
 }
 
 #[derive(Debug)]
@@ -123,6 +174,7 @@ struct RenderedMenu {
     log_size: Logical,
     phy_size: Physical,
     rows: Vec<OpenMenuRow>,
+    arrow_height: i32,
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -146,6 +198,11 @@ impl Drop for MenuBuffer {
 
 impl Drop for OpenMenu {
     fn drop(&mut self) {
+        if !self.closed_sent {
+            let mut closed = vec![];
+            self.collect_closed(&mut closed);
+            self.sni.menus_closed(&closed);
+        }
         self.child = None;
         self.xdg_popup.destroy();
         self.xdg_surface.destroy();
@@ -177,11 +234,14 @@ impl Menu {
             id: delta.menu_id,
             separator: false,
             label: None,
+            access_key: None,
             enabled: false,
             visible: false,
             icon_template: Default::default(),
             toggle_type: None,
-            toggle_active: false,
+            toggle_state: Default::default(),
+            shortcut: None,
+            disposition: Default::default(),
             submenu: None,
         });
         if let Some(p) = &delta.properties {
@@ -191,6 +251,9 @@ impl Menu {
             if let Some(v) = &p.label {
                 item.label = v.is_not_empty().then(|| v.clone());
             }
+            if let Some(v) = p.access_key {
+                item.access_key = v;
+            }
             if let Some(v) = p.enabled {
                 item.enabled = v;
             }
@@ -201,7 +264,13 @@ impl Menu {
                 item.toggle_type = v;
             }
             if let Some(v) = p.toggle_state {
-                item.toggle_active = v;
+                item.toggle_state = v;
+            }
+            if let Some(v) = &p.shortcut {
+                item.shortcut = v.first().map(|keys| format_shortcut(keys));
+            }
+            if let Some(v) = p.disposition {
+                item.disposition = v;
             }
             if let Some(v) = &p.icon_name {
                 item.icon_template
@@ -286,16 +355,29 @@ impl MenuInstance {
             return Ok(None);
         };
         let positioner = s.xdg_wm_base.create_positioner(&s.qh, ());
-        positioner.set_anchor_rect(0, 0, tray_item.size.0, tray_item.size.1);
+        let (x, y) = tray_item.item.popup_origin();
+        positioner.set_anchor_rect(x, y, tray_item.size.0, tray_item.size.1);
         positioner.set_anchor(tray_item.preferred_anchor);
         positioner.set_gravity(tray_item.preferred_gravity);
         positioner.set_size(rendered.log_size.0, rendered.log_size.1);
-        positioner
-            .set_constraint_adjustment(ConstraintAdjustment::SlideX | ConstraintAdjustment::FlipY);
-        let open = open(tray_item.id, submenu, None, positioner, s, rendered)?;
+        let mut adjustment = ConstraintAdjustment::SlideX | ConstraintAdjustment::FlipY;
+        if settings::get().menu.scrollable {
+            adjustment |= ConstraintAdjustment::ResizeY;
+        }
+        positioner.set_constraint_adjustment(adjustment);
+        let open = open(
+            &tray_item.sni,
+            tray_item.id,
+            root,
+            submenu,
+            None,
+            positioner,
+            s,
+            rendered,
+        )?;
         tray_item
             .item
-            .get_popup(&open.xdg_popup, seat.wl_seat(), serial);
+            .get_popup(&open.xdg_popup, seat.wl_seat(), serial, true);
         open.surface.commit();
         Ok(Some(Self {
             sni: tray_item.sni.clone(),
@@ -304,6 +386,7 @@ impl MenuInstance {
             icon_cache,
             open_call: None,
             open,
+            keyboard_select: None,
         }))
     }
 
@@ -374,10 +457,15 @@ impl MenuInstance {
                 positioner.set_gravity(Gravity::BottomRight);
             }
         }
-        positioner
-            .set_constraint_adjustment(ConstraintAdjustment::FlipX | ConstraintAdjustment::SlideY);
+        let mut adjustment = ConstraintAdjustment::FlipX | ConstraintAdjustment::SlideY;
+        if settings::get().menu.scrollable {
+            adjustment |= ConstraintAdjustment::ResizeY;
+        }
+        positioner.set_constraint_adjustment(adjustment);
         let open = open(
+            &self.sni,
             self.tray_item,
+            root,
             submenu,
             Some(&parent.xdg_surface),
             positioner,
@@ -385,7 +473,18 @@ impl MenuInstance {
             rendered,
         )?;
         open.surface.commit();
-        parent.child = Some(Box::new(open));
+        let child = parent.child.insert(Box::new(open));
+        if let Some((seat, _)) = self.keyboard_select.take_if(|(_, menu)| *menu == id) {
+            child.handle_key(
+                seat,
+                MenuKey::Home,
+                &self.sni,
+                root,
+                s,
+                &mut self.icon_cache,
+                self.scale,
+            );
+        }
         Ok(())
     }
 
@@ -395,13 +494,23 @@ impl MenuInstance {
         delta: &SniMenuDelta,
         singletons: &Singletons,
     ) -> bool {
+        self.open.apply_delta(delta);
+        self.rerender(root, singletons)
+    }
+
+    /// Re-renders all open menus with the current settings.
+    pub fn handle_settings_changed(&mut self, root: &Menu, singletons: &Singletons) -> bool {
+        self.open.invalidate();
+        self.rerender(root, singletons)
+    }
+
+    fn rerender(&mut self, root: &Menu, singletons: &Singletons) -> bool {
         let Some(menu) = root.items.get(&0) else {
             return false;
         };
         let Some(menu) = &menu.submenu else {
             return false;
         };
-        self.open.apply_delta(delta);
         let rerendered =
             self.open
                 .maybe_rerender(&mut self.icon_cache, self.scale, root, menu, singletons);
@@ -446,10 +555,24 @@ impl MenuInstance {
         })
     }
 
-    pub fn handle_button(&mut self, seat: &Seat, root: &Menu, menu: MenuId) -> bool {
+    pub fn handle_button(
+        &mut self,
+        seat: &Seat,
+        root: &Menu,
+        s: &Singletons,
+        menu: MenuId,
+    ) -> bool {
         let Some(menu) = self.open.find_menu_mut(menu) else {
             return false;
         };
+        let arrow = menu
+            .seat_position
+            .get(&seat.name())
+            .and_then(|&y| menu.arrow_at(y));
+        if let Some(dir) = arrow {
+            menu.scroll_by(dir, root, s, &mut self.icon_cache, self.scale);
+            return false;
+        }
         let Some(target) = menu.seat_hover.get(&seat.name()) else {
             return false;
         };
@@ -463,6 +586,72 @@ impl MenuInstance {
         true
     }
 
+    pub fn handle_key(
+        &mut self,
+        seat: &Seat,
+        root: &Menu,
+        s: &Singletons,
+        key: MenuKey,
+    ) -> MenuKeyResult {
+        let action = self.open.handle_key(
+            seat.name(),
+            key,
+            &self.sni,
+            root,
+            s,
+            &mut self.icon_cache,
+            self.scale,
+        );
+        match action {
+            KeyAction::None | KeyAction::Back => {}
+            KeyAction::Close => return MenuKeyResult::Close,
+            KeyAction::Activate(id) => {
+                self.sni.menu_clicked(id);
+                return MenuKeyResult::Activated;
+            }
+            KeyAction::OpenChild(id) => {
+                if let Some(child) = self.open.find_menu_mut(id) {
+                    child.handle_key(
+                        seat.name(),
+                        MenuKey::Home,
+                        &self.sni,
+                        root,
+                        s,
+                        &mut self.icon_cache,
+                        self.scale,
+                    );
+                } else {
+                    self.keyboard_select = Some((seat.name(), id));
+                    self.hover_child(seat.name(), root, s, id);
+                }
+            }
+        }
+        MenuKeyResult::None
+    }
+
+    pub fn handle_scroll(
+        &mut self,
+        seat: &Seat,
+        root: &Menu,
+        s: &Singletons,
+        menu_id: MenuId,
+        steps: i32,
+    ) {
+        let Some(open) = self.open.find_menu_mut(menu_id) else {
+            return;
+        };
+        open.scroll_by(steps, root, s, &mut self.icon_cache, self.scale);
+        let new = open.handle_seat_position(root, s, seat.name(), &mut self.icon_cache, self.scale);
+        if let Some(new) = new {
+            self.hover_child(seat.name(), root, s, new);
+        }
+    }
+
+    pub fn handle_geometry(&mut self, root: &Menu, s: &Singletons, menu_id: MenuId, height: i32) {
+        self.open
+            .handle_geometry(menu_id, height, root, s, &mut self.icon_cache, self.scale);
+    }
+
     pub fn handle_motion(
         &mut self,
         seat: &Seat,
@@ -504,6 +693,14 @@ impl MenuInstance {
 }
 
 impl OpenMenu {
+    fn collect_closed(&mut self, closed: &mut Vec<MenuId>) {
+        self.closed_sent = true;
+        if let Some(child) = &mut self.child {
+            child.collect_closed(closed);
+        }
+        closed.push(self.id);
+    }
+
     pub fn find_surface(&self, surface: &WlSurface) -> Option<MenuId> {
         if &self.surface == surface {
             return Some(self.id);
@@ -614,10 +811,13 @@ impl OpenMenu {
                         if let Some(p) = &d.properties {
                             needs_render |= p.separator.is_some();
                             needs_render |= p.label.is_some();
+                            needs_render |= p.access_key.is_some();
                             needs_render |= p.enabled.is_some();
                             needs_render |= p.visible.is_some();
                             needs_render |= p.toggle_type.is_some();
                             needs_render |= p.toggle_state.is_some();
+                            needs_render |= p.shortcut.is_some();
+                            needs_render |= p.disposition.is_some();
                             needs_render |= p.icon_name.is_some();
                             needs_render |= p.icon_png.is_some();
                         }
@@ -637,6 +837,16 @@ impl OpenMenu {
         self.needs_render |= needs_render;
     }
 
+    fn invalidate(&mut self) {
+        self.needs_render = true;
+        if !settings::get().menu.scrollable {
+            self.max_height = None;
+        }
+        if let Some(child) = &mut self.child {
+            child.invalidate();
+        }
+    }
+
     fn maybe_rerender(
         &mut self,
         icon_cache: &mut AHashMap<MenuId, CairoIcon>,
@@ -666,6 +876,9 @@ impl OpenMenu {
             let Some(rendered) = rendered else {
                 return Ok(false);
             };
+            let content_log_size = rendered.log_size;
+            self.content_rows = rendered.rows.clone();
+            let rendered = self.clip(rendered, scale)?;
             let create_buffer = || {
                 create_buffer(self.tray_item, menu, s, &rendered).map_err(MenuError::CreateMemfd)
             };
@@ -685,18 +898,20 @@ impl OpenMenu {
                     .write_all(&rendered.buffer)
                     .map_err(MenuError::UpdateMemfd)?;
             }
-            if rendered.log_size != self.log_size && self.can_reposition {
+            if content_log_size != self.content_log_size && self.can_reposition {
                 self.next_reposition = self.next_reposition.wrapping_add(1);
                 self.awaiting_reposition = Some(self.next_reposition);
                 self.is_configured = false;
                 self.positioner
-                    .set_size(rendered.log_size.0, rendered.log_size.1);
+                    .set_size(content_log_size.0, content_log_size.1);
                 self.xdg_popup
                     .reposition(&self.positioner, self.next_reposition);
             }
+            self.content_log_size = content_log_size;
             self.log_size = rendered.log_size;
             self.phy_size = rendered.phy_size;
             self.rows = rendered.rows;
+            self.arrow_height = rendered.arrow_height;
             if self.is_configured {
                 self.swap();
             } else {
@@ -719,6 +934,194 @@ impl OpenMenu {
         Ok(true)
     }
 
+    /// Limits the menu to the height chosen by the compositor.
+    ///
+    /// If the menu does not fit, only the part starting at the scroll position is shown
+    /// and arrows are drawn above and below it.
+    fn clip(&mut self, rendered: RenderedMenu, scale: Scale) -> Result<RenderedMenu, MenuError> {
+        let Some(max_height) = self.max_height else {
+            self.scroll = 0;
+            return Ok(rendered);
+        };
+        if rendered.log_size.1 <= max_height {
+            self.scroll = 0;
+            return Ok(rendered);
+        }
+        let row_height = row_height(&rendered.rows);
+        let arrow_height = (row_height / 2).max(1);
+        let view_height = max_height - 2 * arrow_height;
+        if view_height <= 0 {
+            return Ok(rendered);
+        }
+        self.scroll = self.scroll.clamp(0, rendered.log_size.1 - view_height);
+        let settings = settings::get();
+        let wlscale = scale.to_f64();
+        let scalef = wlscale * settings.scale;
+        let log = Logical(rendered.log_size.0, max_height);
+        let phy = log.to_physical(scale);
+        let mut content =
+            cairo::ImageSurface::create(Format::ARgb32, rendered.phy_size.0, rendered.phy_size.1)?;
+        content.data()?.copy_from_slice(&rendered.buffer);
+        let mut surface = cairo::ImageSurface::create(Format::ARgb32, phy.0, phy.1)?;
+        let cairo = cairo::Context::new(&surface)?;
+
+        // background
+        settings.menu.background_color.set(&cairo);
+        cairo.paint()?;
+
+        // content
+        let arrow_phy = arrow_height as f64 * wlscale;
+        cairo.save()?;
+        cairo.rectangle(0.0, arrow_phy, phy.0 as f64, view_height as f64 * wlscale);
+        cairo.clip();
+        cairo.set_source_surface(&content, 0.0, (arrow_height - self.scroll) as f64 * wlscale)?;
+        cairo.paint()?;
+        cairo.restore()?;
+
+        // arrows
+        let line_width = scalef.round();
+        let dd = (arrow_phy / 2.0).floor();
+        for (y_center, dir, enabled) in [
+            (arrow_phy / 2.0, -1.0, self.scroll > 0),
+            (
+                phy.1 as f64 - arrow_phy / 2.0,
+                1.0,
+                self.scroll < rendered.log_size.1 - view_height,
+            ),
+        ] {
+            cairo.move_to(phy.0 as f64 / 2.0 - dd, y_center - dir * dd / 2.0);
+            cairo.rel_line_to(dd, dir * dd);
+            cairo.rel_line_to(dd, -dir * dd);
+            match enabled {
+                true => settings.menu.color.set(&cairo),
+                false => settings.menu.disabled_color.set(&cairo),
+            }
+            cairo.set_line_width(line_width);
+            cairo.set_line_cap(LineCap::Round);
+            cairo.stroke()?;
+        }
+
+        // border
+        let border_width = (settings.menu.border_width * scalef).round();
+        let bw2 = border_width / 2.0;
+        cairo.rectangle(
+            bw2,
+            bw2,
+            phy.0 as f64 - border_width,
+            phy.1 as f64 - border_width,
+        );
+        cairo.set_line_width(border_width);
+        settings.menu.border_color.set(&cairo);
+        cairo.stroke()?;
+
+        drop(cairo);
+        surface.flush();
+        let buffer = surface.data()?.to_vec();
+
+        let top = arrow_height;
+        let bottom = arrow_height + view_height;
+        let rows = rendered
+            .rows
+            .iter()
+            .filter_map(|r| {
+                let y1 = (r.y1 - self.scroll + arrow_height).max(top);
+                let y2 = (r.y2 - self.scroll + arrow_height).min(bottom);
+                (y1 < y2).then_some(OpenMenuRow {
+                    y1,
+                    y2,
+                    menu_id: r.menu_id,
+                })
+            })
+            .collect();
+        Ok(RenderedMenu {
+            buffer,
+            log_space_top: rendered.log_space_top,
+            log_size: log,
+            phy_size: phy,
+            rows,
+            arrow_height,
+        })
+    }
+
+    fn handle_geometry(
+        &mut self,
+        id: MenuId,
+        height: i32,
+        root: &Menu,
+        s: &Singletons,
+        icon_cache: &mut AHashMap<MenuId, CairoIcon>,
+        scale: Scale,
+    ) {
+        if self.id != id {
+            if let Some(child) = &mut self.child {
+                child.handle_geometry(id, height, root, s, icon_cache, scale);
+            }
+            return;
+        }
+        if !settings::get().menu.scrollable || height <= 0 {
+            return;
+        }
+        let max_height = (height < self.content_log_size.1).then_some(height);
+        if self.max_height == max_height {
+            return;
+        }
+        self.max_height = max_height;
+        self.is_configured = false;
+        self.needs_render = true;
+        if let Some(sub) = root.items.get(&self.id).and_then(|m| m.submenu.as_ref()) {
+            self.maybe_rerender(icon_cache, scale, root, sub, s);
+        }
+    }
+
+    /// Returns the direction of the scroll arrow at the position.
+    fn arrow_at(&self, y: i32) -> Option<i32> {
+        if self.arrow_height == 0 {
+            return None;
+        }
+        if y < self.arrow_height {
+            return Some(-1);
+        }
+        if y >= self.log_size.1 - self.arrow_height {
+            return Some(1);
+        }
+        None
+    }
+
+    fn scroll_by(
+        &mut self,
+        steps: i32,
+        root: &Menu,
+        s: &Singletons,
+        icon_cache: &mut AHashMap<MenuId, CairoIcon>,
+        scale: Scale,
+    ) {
+        if self.arrow_height == 0 || steps == 0 {
+            return;
+        }
+        self.scroll += steps * row_height(&self.content_rows);
+        self.child = None;
+        self.needs_render = true;
+        if let Some(sub) = root.items.get(&self.id).and_then(|m| m.submenu.as_ref()) {
+            self.maybe_rerender(icon_cache, scale, root, sub, s);
+        }
+    }
+
+    /// Adjusts the scroll position so that the row is fully visible.
+    fn scroll_into_view(&mut self, id: MenuId) {
+        if self.arrow_height == 0 {
+            return;
+        }
+        let Some(row) = self.content_rows.iter().find(|r| r.menu_id == id) else {
+            return;
+        };
+        let view_height = self.log_size.1 - 2 * self.arrow_height;
+        if row.y1 < self.scroll {
+            self.scroll = row.y1;
+        } else if row.y2 > self.scroll + view_height {
+            self.scroll = row.y2 - view_height;
+        }
+    }
+
     fn swap(&mut self) {
         mem::swap(&mut self.front_buffer, &mut self.back_buffer);
         self.front_buffer.free = false;
@@ -729,6 +1132,122 @@ impl OpenMenu {
         self.surface.commit();
     }
 
+    #[expect(clippy::too_many_arguments)]
+    fn handle_key(
+        &mut self,
+        seat_name: u32,
+        key: MenuKey,
+        sni: &Arc<SniItem>,
+        root: &Menu,
+        s: &Singletons,
+        icon_cache: &mut AHashMap<MenuId, CairoIcon>,
+        scale: Scale,
+    ) -> KeyAction {
+        if let Some(child) = &mut self.child {
+            if child.seat_hover.contains_key(&seat_name) {
+                return match child.handle_key(seat_name, key, sni, root, s, icon_cache, scale) {
+                    KeyAction::Back | KeyAction::Close => {
+                        self.child = None;
+                        KeyAction::None
+                    }
+                    action => action,
+                };
+            }
+        }
+        let Some(sub) = root.items.get(&self.id).and_then(|m| m.submenu.as_ref()) else {
+            return KeyAction::None;
+        };
+        let selectable: Vec<_> = sub
+            .items
+            .iter()
+            .filter_map(|id| root.items.get(id))
+            .filter(|item| item.visible && item.enabled && !item.separator)
+            .collect();
+        let current = self
+            .seat_hover
+            .get(&seat_name)
+            .and_then(|id| selectable.iter().position(|item| item.id == *id));
+        let activate = |item: &MenuItem| match item.submenu.is_some() {
+            true => KeyAction::OpenChild(item.id),
+            false => KeyAction::Activate(item.id),
+        };
+        let last = selectable.len().checked_sub(1);
+        let target = match key {
+            MenuKey::Up => match current {
+                Some(0) | None => last,
+                Some(n) => Some(n - 1),
+            },
+            MenuKey::Down => match current {
+                None => last.map(|_| 0),
+                Some(n) => Some((n + 1) % selectable.len()),
+            },
+            MenuKey::Home => last.map(|_| 0),
+            MenuKey::End => last,
+            MenuKey::Left => return KeyAction::Back,
+            MenuKey::Escape => return KeyAction::Close,
+            MenuKey::Right => {
+                return match current.map(|n| selectable[n]) {
+                    Some(item) if item.submenu.is_some() => KeyAction::OpenChild(item.id),
+                    _ => KeyAction::None,
+                };
+            }
+            MenuKey::Activate => {
+                return match current.map(|n| selectable[n]) {
+                    Some(item) => activate(item),
+                    None => KeyAction::None,
+                };
+            }
+            MenuKey::Char(c) => {
+                let matches = |item: &&MenuItem| {
+                    item.access_key
+                        .is_some_and(|k| k.key.to_lowercase().eq(c.to_lowercase()))
+                };
+                let mut candidates = selectable.iter().enumerate().filter(|(_, i)| matches(i));
+                let Some((first, item)) = candidates.next() else {
+                    return KeyAction::None;
+                };
+                if candidates.next().is_none() {
+                    self.select(seat_name, item.id, sni, root, s, icon_cache, scale);
+                    return activate(item);
+                }
+                let next = selectable
+                    .iter()
+                    .enumerate()
+                    .skip(current.map(|n| n + 1).unwrap_or_default())
+                    .find(|(_, i)| matches(i));
+                Some(next.map(|(n, _)| n).unwrap_or(first))
+            }
+        };
+        if let Some(target) = target {
+            let id = selectable[target].id;
+            self.select(seat_name, id, sni, root, s, icon_cache, scale);
+        }
+        KeyAction::None
+    }
+
+    #[expect(clippy::too_many_arguments)]
+    fn select(
+        &mut self,
+        seat_name: u32,
+        id: MenuId,
+        sni: &Arc<SniItem>,
+        root: &Menu,
+        s: &Singletons,
+        icon_cache: &mut AHashMap<MenuId, CairoIcon>,
+        scale: Scale,
+    ) {
+        if self.seat_hover.insert(seat_name, id) == Some(id) {
+            return;
+        }
+        sni.menu_hovered(id);
+        self.scroll_into_view(id);
+        self.child = None;
+        self.needs_render = true;
+        if let Some(sub) = root.items.get(&self.id).and_then(|m| m.submenu.as_ref()) {
+            self.maybe_rerender(icon_cache, scale, root, sub, s);
+        }
+    }
+
     fn handle_seat_position(
         &mut self,
         root: &Menu,
@@ -775,8 +1294,11 @@ fn create_buffer(
     })
 }
 
+#[expect(clippy::too_many_arguments)]
 fn open(
+    sni: &Arc<SniItem>,
     tray_item: TrayItemId,
+    root: &Menu,
     menu: &SubMenu,
     parent: Option<&XdgSurface>,
     positioner: XdgPositioner,
@@ -793,18 +1315,36 @@ fn open(
     let xdg_popup = xdg_surface.get_popup(parent, &positioner, &s.qh, id);
     let create_buffer =
         || create_buffer(tray_item, menu, s, &rendered).map_err(MenuError::CreateMemfd);
+    let front_buffer = create_buffer()?;
+    let back_buffer = create_buffer()?;
+    sni.menu_opened(menu.id);
+    let submenus = menu
+        .items
+        .iter()
+        .filter_map(|id| root.items.get(id))
+        .filter(|item| item.visible && item.enabled && item.submenu.is_some())
+        .map(|item| item.id)
+        .collect();
+    sni.prefetch_menus(menu.id, submenus);
     Ok(OpenMenu {
         id: menu.id,
+        sni: sni.clone(),
+        closed_sent: false,
         tray_item,
         log_size: rendered.log_size,
         phy_size: rendered.phy_size,
-        front_buffer: create_buffer()?,
-        back_buffer: create_buffer()?,
+        front_buffer,
+        back_buffer,
         surface,
         viewport,
         xdg_surface,
         xdg_popup,
         child: None,
+        content_log_size: rendered.log_size,
+        content_rows: rendered.rows.clone(),
+        max_height: None,
+        scroll: 0,
+        arrow_height: rendered.arrow_height,
         rows: rendered.rows,
         next_reposition: 0,
         awaiting_reposition: None,
@@ -833,6 +1373,7 @@ fn render(
     let mut has_icons = false;
     let mut has_submenus = false;
     let mut max_label_width = 0.0f64;
+    let mut max_shortcut_width = 0.0f64;
     let mut max_label_height = 0.0f64;
     let mut num_labels = 0;
     let mut num_separators = 0;
@@ -880,6 +1421,12 @@ fn render(
         }
         max_label_width = max_label_width.max(w);
         max_label_height = max_label_height.max(h);
+        if let Some(shortcut) = &item.shortcut {
+            layout.set_text(shortcut);
+            let (w, h) = layout.size();
+            max_shortcut_width = max_shortcut_width.max(w as f64 / pango_scale);
+            max_label_height = max_label_height.max(h as f64 / pango_scale);
+        }
     }
 
     if num_labels == 0 {
@@ -895,6 +1442,9 @@ fn render(
     if has_submenus {
         phy_width += sub_width + 2.0 * padding;
     }
+    if max_shortcut_width > 0.0 {
+        phy_width += max_shortcut_width + 2.0 * padding;
+    }
     let mut phy_height = padding;
     phy_height += 2.0 * border_width;
     phy_height += (max_label_height + padding) * num_labels as f64;
@@ -932,7 +1482,12 @@ fn render(
             y += line_width;
         } else {
             let mut x = border_width + padding;
-            let mut color = &settings.menu.color;
+            let mut color = match item.disposition {
+                SniMenuDisposition::Normal => &settings.menu.color,
+                SniMenuDisposition::Informative => &settings.menu.informative_color,
+                SniMenuDisposition::Warning => &settings.menu.warning_color,
+                SniMenuDisposition::Alert => &settings.menu.alert_color,
+            };
             if !item.enabled {
                 color = &settings.menu.disabled_color;
             } else if hovered.values().any(|v| *v == item.id) {
@@ -996,17 +1551,25 @@ fn render(
                         color.set(&cairo);
                         cairo.set_line_width(line_width);
                         cairo.stroke()?;
-                        if item.toggle_active {
-                            cairo.move_to(x + box_width - 5.0 * line_width / 2.0, y_center);
-                            cairo.arc(
-                                x + box_width / 2.0,
-                                y_center,
-                                (box_width - 5.0 * line_width) / 2.0,
-                                0.0,
-                                2.0 * PI,
-                            );
-                            color.set(&cairo);
-                            cairo.fill()?;
+                        match item.toggle_state {
+                            SniMenuToggleState::Off => {}
+                            SniMenuToggleState::On => {
+                                cairo.move_to(x + box_width - 5.0 * line_width / 2.0, y_center);
+                                cairo.arc(
+                                    x + box_width / 2.0,
+                                    y_center,
+                                    (box_width - 5.0 * line_width) / 2.0,
+                                    0.0,
+                                    2.0 * PI,
+                                );
+                                color.set(&cairo);
+                                cairo.fill()?;
+                            }
+                            SniMenuToggleState::Indeterminate => {
+                                draw_indeterminate(
+                                    &cairo, color, x, y_center, box_width, line_width,
+                                )?;
+                            }
                         }
                     }
                     SniMenuToggleType::Checkmark => {
@@ -1020,17 +1583,30 @@ fn render(
                         cairo.set_line_width(line_width);
                         cairo.set_line_cap(LineCap::Square);
                         cairo.stroke()?;
-                        if item.toggle_active {
-                            let line_width = 1.2 * line_width;
-                            let inset = 6.0 * line_width / 2.0;
-                            cairo.move_to(x + inset, y_center);
-                            cairo.line_to(x + box_width / 2.0, y_center + box_width / 2.0 - inset);
-                            cairo
-                                .line_to(x + box_width - inset, y_center - box_width / 2.0 + inset);
-                            color.set(&cairo);
-                            cairo.set_line_width(line_width);
-                            cairo.set_line_cap(LineCap::Round);
-                            cairo.stroke()?;
+                        match item.toggle_state {
+                            SniMenuToggleState::Off => {}
+                            SniMenuToggleState::On => {
+                                let line_width = 1.2 * line_width;
+                                let inset = 6.0 * line_width / 2.0;
+                                cairo.move_to(x + inset, y_center);
+                                cairo.line_to(
+                                    x + box_width / 2.0,
+                                    y_center + box_width / 2.0 - inset,
+                                );
+                                cairo.line_to(
+                                    x + box_width - inset,
+                                    y_center - box_width / 2.0 + inset,
+                                );
+                                color.set(&cairo);
+                                cairo.set_line_width(line_width);
+                                cairo.set_line_cap(LineCap::Round);
+                                cairo.stroke()?;
+                            }
+                            SniMenuToggleState::Indeterminate => {
+                                draw_indeterminate(
+                                    &cairo, color, x, y_center, box_width, line_width,
+                                )?;
+                            }
                         }
                     }
                 }
@@ -1038,6 +1614,26 @@ fn render(
             }
             if let Some(label) = &item.label {
                 layout.set_text(label);
+                if let Some(key) = item.access_key {
+                    let attrs = pango::AttrList::new();
+                    let mut underline = pango::AttrInt::new_underline(pango::Underline::Single);
+                    underline.set_start_index(key.offset as _);
+                    underline.set_end_index((key.offset + key.key.len_utf8()) as _);
+                    attrs.insert(underline);
+                    layout.set_attributes(Some(&attrs));
+                }
+                cairo.move_to(x, y);
+                color.set(&cairo);
+                show_layout(&cairo, &layout);
+                layout.set_attributes(None);
+            }
+            if let Some(shortcut) = &item.shortcut {
+                layout.set_text(shortcut);
+                let (w, _) = layout.size();
+                let mut x = phy.0 as f64 - border_width - padding - w as f64 / pango_scale;
+                if !settings.menu.rtl && has_submenus {
+                    x -= sub_width + 2.0 * padding;
+                }
                 cairo.move_to(x, y);
                 color.set(&cairo);
                 show_layout(&cairo, &layout);
@@ -1094,5 +1690,43 @@ fn render(
         log_size: log,
         phy_size: phy,
         rows,
+        arrow_height: 0,
     }))
 }
+
+fn draw_indeterminate(
+    cairo: &cairo::Context,
+    color: &ThemeColor,
+    x: f64,
+    y_center: f64,
+    box_width: f64,
+    line_width: f64,
+) -> Result<(), cairo::Error> {
+    let inset = 5.0 * line_width / 2.0;
+    cairo.move_to(x + inset, y_center);
+    cairo.line_to(x + box_width - inset, y_center);
+    color.set(cairo);
+    cairo.set_line_width(1.2 * line_width);
+    cairo.set_line_cap(LineCap::Round);
+    cairo.stroke()
+}
+
+/// Formats a DBusMenu key combination such as `["Control", "Shift", "q"]`.
+fn format_shortcut(keys: &[String]) -> String {
+    let mut res = String::new();
+    for key in keys {
+        if res.is_not_empty() {
+            res.push('+');
+        }
+        match &**key {
+            "Control" => res.push_str("Ctrl"),
+            k if k.chars().count() == 1 => res.extend(k.chars().flat_map(|c| c.to_uppercase())),
+            k => res.push_str(k),
+        }
+    }
+    res
+}
+
+fn row_height(rows: &[OpenMenuRow]) -> i32 {
+    rows.iter().map(|r| r.y2 - r.y1).max().unwrap_or(1)
+}
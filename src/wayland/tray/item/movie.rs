@@ -0,0 +1,231 @@
+use {
+    crate::wayland::{
+        tray::item::icon::{find_icon_file, BufferIconFrame},
+        utils::create_shm_buf_oneshot,
+        Singletons,
+    },
+    error_reporter::Report,
+    isnt::std_1::vec::IsntVecExt,
+    png::{BlendOp, ColorType, DisposeOp, OutputInfo, Transformations},
+    std::{io, path::Path, sync::Arc, time::Duration},
+};
+
+/// The delay between frames of movies that are stored as a directory of images.
+const DIRECTORY_FRAME_DELAY: Duration = Duration::from_millis(100);
+
+pub struct Movie {
+    pub frames: Vec<MovieFrame>,
+}
+
+pub struct MovieFrame {
+    /// Premultiplied BGRA.
+    pub bytes: Vec<u8>,
+    pub size: (i32, i32),
+    pub delay: Duration,
+}
+
+#[derive(Default)]
+pub struct MovieBuffers {
+    movie: Option<Arc<Movie>>,
+    frames: Vec<(BufferIconFrame, (i32, i32))>,
+}
+
+impl MovieBuffers {
+    pub fn get(&self, frame: usize) -> Option<&(BufferIconFrame, (i32, i32))> {
+        self.frames.get(frame)
+    }
+
+    pub fn update(&mut self, movie: &Arc<Movie>, s: &Singletons) {
+        if let Some(m) = &self.movie {
+            if Arc::ptr_eq(m, movie) {
+                return;
+            }
+        }
+        self.movie = Some(movie.clone());
+        self.frames.clear();
+        for frame in &movie.frames {
+            match create_shm_buf_oneshot(s, &frame.bytes, frame.size) {
+                Ok(b) => self.frames.push((b.into(), frame.size)),
+                Err(e) => {
+                    log::error!("Could not create movie buffer: {}", Report::new(e));
+                    self.frames.clear();
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// Loads an `AttentionMovieName`.
+///
+/// The name can be the path of an animated PNG or of a directory containing PNG frames.
+/// Otherwise it is looked up like an icon name.
+pub fn load_movie(name: &str, theme_path: Option<&str>, theme: &str) -> Option<Movie> {
+    let mut path = Path::new(name).to_path_buf();
+    if !path.is_absolute() {
+        path = theme_path
+            .map(|dir| Path::new(dir).join(name))
+            .filter(|p| p.is_dir())
+            .or_else(|| find_icon_file(name, 32, 1, theme, theme_path))?;
+    }
+    let res = match path.is_dir() {
+        true => load_directory(&path),
+        false => std::fs::read(&path).and_then(|c| decode_apng(&c).map_err(io::Error::other)),
+    };
+    match res {
+        Ok(frames) if frames.is_not_empty() => Some(Movie { frames }),
+        Ok(_) => None,
+        Err(e) => {
+            log::error!(
+                "Could not load movie {}: {}",
+                path.display(),
+                Report::new(e)
+            );
+            None
+        }
+    }
+}
+
+fn load_directory(dir: &Path) -> Result<Vec<MovieFrame>, io::Error> {
+    let mut paths = vec![];
+    for entry in dir.read_dir()? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|e| e == "png") {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    let mut frames = vec![];
+    for path in paths {
+        let contents = std::fs::read(&path)?;
+        let frame = decode_apng(&contents)
+            .map_err(io::Error::other)?
+            .swap_remove(0);
+        frames.push(MovieFrame {
+            delay: DIRECTORY_FRAME_DELAY,
+            ..frame
+        });
+    }
+    Ok(frames)
+}
+
+fn decode_apng(mut contents: &[u8]) -> Result<Vec<MovieFrame>, png::DecodingError> {
+    let mut decoder = png::Decoder::new(&mut contents);
+    decoder.set_transformations(Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info()?;
+    let (width, height) = reader.info().size();
+    let animated = reader.info().animation_control.is_some();
+    let mut skip_default_image = animated && reader.info().frame_control.is_none();
+    let mut canvas = vec![0u8; width as usize * height as usize * 4];
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let mut frames = vec![];
+    loop {
+        let info = match reader.next_frame(&mut buf) {
+            Ok(i) => i,
+            Err(e) if frames.is_empty() => return Err(e),
+            Err(_) => break,
+        };
+        if skip_default_image {
+            skip_default_image = false;
+            continue;
+        }
+        let fc = reader.info().frame_control.filter(|_| animated);
+        let (x, y, blend, dispose, delay) = match fc {
+            Some(fc) => {
+                let den = match fc.delay_den {
+                    0 => 100,
+                    d => d,
+                };
+                let mut delay = Duration::from_secs(fc.delay_num as u64) / den as u32;
+                if delay < Duration::from_millis(10) {
+                    delay = Duration::from_millis(100);
+                }
+                (fc.x_offset, fc.y_offset, fc.blend_op, fc.dispose_op, delay)
+            }
+            None => (0, 0, BlendOp::Source, DisposeOp::None, Duration::MAX),
+        };
+        let previous = (dispose == DisposeOp::Previous).then(|| canvas.clone());
+        let frame = to_rgba(&buf, &info);
+        for row in 0..info.height.min(height.saturating_sub(y)) {
+            for col in 0..info.width.min(width.saturating_sub(x)) {
+                let src = (row * info.width + col) as usize * 4;
+                let dst = ((y + row) * width + x + col) as usize * 4;
+                let src = &frame[src..src + 4];
+                let dst = &mut canvas[dst..dst + 4];
+                match blend {
+                    BlendOp::Source => dst.copy_from_slice(src),
+                    BlendOp::Over => blend_over(dst, src),
+                }
+            }
+        }
+        let mut bytes = canvas.clone();
+        premultiply_bgra(&mut bytes);
+        frames.push(MovieFrame {
+            bytes,
+            size: (width as i32, height as i32),
+            delay,
+        });
+        match dispose {
+            DisposeOp::None => {}
+            DisposeOp::Background => {
+                for row in 0..info.height.min(height.saturating_sub(y)) {
+                    let start = ((y + row) * width + x) as usize * 4;
+                    let len = info.width.min(width.saturating_sub(x)) as usize * 4;
+                    canvas[start..start + len].fill(0);
+                }
+            }
+            DisposeOp::Previous => {
+                if let Some(previous) = previous {
+                    canvas = previous;
+                }
+            }
+        }
+        if !animated {
+            break;
+        }
+    }
+    Ok(frames)
+}
+
+fn to_rgba(buf: &[u8], info: &OutputInfo) -> Vec<u8> {
+    let samples = info.color_type.samples();
+    let mut res = Vec::with_capacity(info.width as usize * info.height as usize * 4);
+    for line in buf.chunks_exact(info.line_size).take(info.height as usize) {
+        for px in line.chunks_exact(samples).take(info.width as usize) {
+            match info.color_type {
+                ColorType::Grayscale | ColorType::Indexed => {
+                    res.extend_from_slice(&[px[0], px[0], px[0], 255])
+                }
+                ColorType::GrayscaleAlpha => res.extend_from_slice(&[px[0], px[0], px[0], px[1]]),
+                ColorType::Rgb => res.extend_from_slice(&[px[0], px[1], px[2], 255]),
+                ColorType::Rgba => res.extend_from_slice(px),
+            }
+        }
+    }
+    res
+}
+
+fn blend_over(dst: &mut [u8], src: &[u8]) {
+    let sa = src[3] as f32 / 255.0;
+    let da = dst[3] as f32 / 255.0;
+    let a = sa + da * (1.0 - sa);
+    if a == 0.0 {
+        dst.fill(0);
+        return;
+    }
+    for i in 0..3 {
+        let c = (src[i] as f32 * sa + dst[i] as f32 * da * (1.0 - sa)) / a;
+        dst[i] = c.round() as u8;
+    }
+    dst[3] = (a * 255.0).round() as u8;
+}
+
+fn premultiply_bgra(bytes: &mut [u8]) {
+    let mut chunks = bytes.chunks_mut(4);
+    while let Some([r, g, b, a]) = chunks.next() {
+        std::mem::swap(r, b);
+        *r = (*r as f32 * *a as f32 / 255.0) as u8;
+        *g = (*g as f32 * *a as f32 / 255.0) as u8;
+        *b = (*b as f32 * *a as f32 / 255.0) as u8;
+    }
+}
@@ -3,10 +3,16 @@ use {
         settings::{self},
         wayland::{
             scale::{Logical, Scale},
+            tray::item::{
+                icon::{CairoIcon, IconTemplate},
+                tooltip::markup::{escape_into, html_to_markup},
+            },
             utils::create_shm_buf_oneshot,
             Singletons,
         },
     },
+    error_reporter::Report,
+    isnt::std_1::primitive::IsntStrExt,
     pangocairo::{
         cairo::{self, Format, LineCap},
         pango::{self},
@@ -18,6 +24,11 @@ use {
     wayland_protocols::wp::viewporter::client::wp_viewport::WpViewport,
 };
 
+mod markup;
+
+/// The size of the tooltip icon in logical pixels before applying the scale setting.
+const ICON_SIZE: f64 = 32.0;
+
 #[derive(Debug, Error)]
 pub enum TooltipError {
     #[error(transparent)]
@@ -28,6 +39,14 @@ pub enum TooltipError {
     CreateMemfd(#[source] io::Error),
 }
 
+/// The contents of a tooltip.
+pub struct TooltipContent<'a> {
+    pub title: &'a str,
+    /// May contain the HTML subset allowed by the SNI spec.
+    pub text: &'a str,
+    pub icon: &'a IconTemplate,
+}
+
 pub struct Tooltip {
     pub buffer: WlBuffer,
     pub surface: WlSurface,
@@ -43,8 +62,12 @@ impl Drop for Tooltip {
     }
 }
 
-pub fn create_tooltip(s: &Singletons, scale: Scale, text: &str) -> Result<Tooltip, TooltipError> {
-    let (buffer, log) = draw(s, scale, text)?;
+pub fn create_tooltip(
+    s: &Singletons,
+    scale: Scale,
+    content: &TooltipContent<'_>,
+) -> Result<Tooltip, TooltipError> {
+    let (buffer, log) = draw(s, scale, content)?;
     let surface = s.wl_compositor.create_surface(&s.qh, ());
     let viewport = s.wp_viewporter.get_viewport(&surface, &s.qh, ());
     Ok(Tooltip {
@@ -55,7 +78,27 @@ pub fn create_tooltip(s: &Singletons, scale: Scale, text: &str) -> Result<Toolti
     })
 }
 
-fn draw(s: &Singletons, scale: Scale, text: &str) -> Result<(WlBuffer, Logical), TooltipError> {
+fn markup(content: &TooltipContent<'_>) -> String {
+    if content.text.is_empty() {
+        let mut res = String::new();
+        escape_into(&mut res, content.title);
+        return res;
+    }
+    let mut res = String::new();
+    if content.title.is_not_empty() {
+        res.push_str("<b>");
+        escape_into(&mut res, content.title);
+        res.push_str("</b>\n");
+    }
+    res.push_str(&html_to_markup(content.text));
+    res
+}
+
+fn draw(
+    s: &Singletons,
+    scale: Scale,
+    content: &TooltipContent<'_>,
+) -> Result<(WlBuffer, Logical), TooltipError> {
     let settings = settings::get();
     let wlscale = scale.to_f64();
     let scalef = wlscale * settings.scale;
@@ -63,14 +106,46 @@ fn draw(s: &Singletons, scale: Scale, text: &str) -> Result<(WlBuffer, Logical),
     ctx.set_font_map(Some(&FontMap::default()));
     let mut font = settings.tooltip.font.clone();
     font.set_size((font.size() as f64 * scalef).round() as _);
+    let padding = settings.tooltip.padding * scalef;
+    let mut icon = CairoIcon::default();
+    let mut icon_size = 0.0;
+    if content.icon.is_some() {
+        icon_size = (ICON_SIZE * scalef).round();
+        icon.update(
+            content.icon,
+            (icon_size as i32, icon_size as i32),
+            scalef.ceil() as _,
+            &settings.theme,
+            &settings.tooltip.color,
+        );
+    }
+    let icon = icon.get();
+    let text_x = match icon.is_some() {
+        true => icon_size + 2.0 * padding,
+        false => padding,
+    };
     let layout = pango::Layout::new(&ctx);
     layout.set_font_description(Some(&font));
-    layout.set_text(text);
+    let markup = markup(content);
+    match pango::parse_markup(&markup, '\0') {
+        Ok(_) => layout.set_markup(&markup),
+        Err(e) => {
+            log::warn!("Could not parse tooltip markup: {}", Report::new(e));
+            layout.set_text(content.title);
+        }
+    }
+    if settings.tooltip.max_width > 0.0 {
+        let max_width = settings.tooltip.max_width * scalef - text_x - padding;
+        layout.set_width((max_width.max(1.0) * pango::SCALE as f64) as i32);
+        match settings.tooltip.wrap {
+            true => layout.set_wrap(pango::WrapMode::WordChar),
+            false => layout.set_ellipsize(pango::EllipsizeMode::End),
+        }
+    }
     let (width, height) = layout.pixel_size();
-    let padding = settings.tooltip.padding * scalef;
     let log = Logical(
-        ((width as f64 + 2.0 * padding) / wlscale).round() as i32,
-        ((height as f64 + 2.0 * padding) / wlscale).round() as i32,
+        ((text_x + width as f64 + padding) / wlscale).round() as i32,
+        (((height as f64).max(icon_size) + 2.0 * padding) / wlscale).round() as i32,
     );
     let phy = log.to_physical(scale);
     let mut surface = cairo::ImageSurface::create(Format::ARgb32, phy.0, phy.1)?;
@@ -82,9 +157,22 @@ fn draw(s: &Singletons, scale: Scale, text: &str) -> Result<(WlBuffer, Logical),
         cairo.set_source_rgba(c.r, c.g, c.b, c.a);
         cairo.paint()?;
 
+        // icon
+        if let Some(surface) = &icon {
+            let pattern = cairo::SurfacePattern::create(surface);
+            cairo.translate(padding, padding);
+            cairo.scale(
+                icon_size / surface.width() as f64,
+                icon_size / surface.height() as f64,
+            );
+            cairo.set_source(&pattern)?;
+            cairo.paint()?;
+            cairo.identity_matrix();
+        }
+
         // text
         settings.tooltip.color.set(&cairo);
-        cairo.move_to(padding, padding);
+        cairo.move_to(text_x, padding);
         pangocairo::functions::show_layout(&cairo, &layout);
 
         // border
@@ -0,0 +1,151 @@
+/// Converts the HTML subset allowed in SNI tooltips to Pango markup.
+///
+/// `b`, `i`, and `u` are kept, `br` becomes a line break, the contents of `a` are
+/// underlined, and `img` is dropped. Other tags are dropped but their contents are kept.
+/// Unclosed tags are closed at the end of the text.
+pub fn html_to_markup(html: &str) -> String {
+    let mut res = String::with_capacity(html.len());
+    let mut open = vec![];
+    let mut rest = html;
+    while let Some(c) = rest.chars().next() {
+        match c {
+            '<' if rest[1..].starts_with(|c: char| c.is_ascii_alphabetic() || c == '/') => {
+                let Some(end) = rest.find('>') else {
+                    escape_into(&mut res, rest);
+                    break;
+                };
+                handle_tag(&mut res, &mut open, &rest[1..end]);
+                rest = &rest[end + 1..];
+            }
+            '&' => {
+                let entity = rest[1..]
+                    .find(';')
+                    .filter(|&end| end <= 8)
+                    .and_then(|end| Some((decode_entity(&rest[1..end + 1])?, end)));
+                match entity {
+                    Some((c, end)) => {
+                        escape_into(&mut res, c.encode_utf8(&mut [0; 4]));
+                        rest = &rest[end + 2..];
+                    }
+                    None => {
+                        res.push_str("&amp;");
+                        rest = &rest[1..];
+                    }
+                }
+            }
+            _ => {
+                escape_into(&mut res, &rest[..c.len_utf8()]);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+    while let Some(tag) = open.pop() {
+        close_tag(&mut res, tag);
+    }
+    res
+}
+
+/// Escapes plain text for use in Pango markup.
+pub fn escape_into(res: &mut String, text: &str) {
+    for c in text.chars() {
+        match c {
+            '&' => res.push_str("&amp;"),
+            '<' => res.push_str("&lt;"),
+            '>' => res.push_str("&gt;"),
+            '"' => res.push_str("&quot;"),
+            '\'' => res.push_str("&apos;"),
+            _ => res.push(c),
+        }
+    }
+}
+
+fn handle_tag(res: &mut String, open: &mut Vec<&'static str>, tag: &str) {
+    let (closing, tag) = match tag.strip_prefix('/') {
+        Some(t) => (true, t),
+        None => (false, tag),
+    };
+    let self_closing = tag.ends_with('/');
+    let name = tag
+        .split(|c: char| c.is_whitespace() || c == '/')
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    let pango = match &*name {
+        "b" | "strong" => "b",
+        "i" | "em" => "i",
+        "u" | "a" => "u",
+        "br" => {
+            if !closing {
+                res.push('\n');
+            }
+            return;
+        }
+        _ => return,
+    };
+    if closing {
+        if let Some(pos) = open.iter().rposition(|t| *t == pango) {
+            for tag in open.drain(pos..).rev() {
+                close_tag(res, tag);
+            }
+        }
+    } else if !self_closing {
+        res.push('<');
+        res.push_str(pango);
+        res.push('>');
+        open.push(pango);
+    }
+}
+
+fn close_tag(res: &mut String, tag: &str) {
+    res.push_str("</");
+    res.push_str(tag);
+    res.push('>');
+}
+
+fn decode_entity(entity: &str) -> Option<char> {
+    let c = match entity {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => '\u{a0}',
+        _ => {
+            let n = entity.strip_prefix('#')?;
+            let n = match n.strip_prefix(['x', 'X']) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                None => n.parse().ok()?,
+            };
+            return char::from_u32(n);
+        }
+    };
+    Some(c)
+}
+
+#[test]
+fn special_characters_are_escaped() {
+    assert_eq!(
+        html_to_markup(r#"a > b & "c" 'd' < e"#),
+        "a &gt; b &amp; &quot;c&quot; &apos;d&apos; &lt; e",
+    );
+}
+
+#[test]
+fn entities_are_decoded() {
+    assert_eq!(
+        html_to_markup("&#65;&#x42;&lt;&quot;&nbsp;&bogus;&"),
+        "AB&lt;&quot;\u{a0}&amp;bogus;&amp;",
+    );
+}
+
+#[test]
+fn tags_are_closed_in_order() {
+    assert_eq!(html_to_markup("<b><i>x</b>y"), "<b><i>x</i></b>y");
+    assert_eq!(html_to_markup("<em>x<br/>y"), "<i>x\ny</i>");
+    assert_eq!(html_to_markup("<span>x</span></u>"), "x");
+}
+
+#[test]
+fn unterminated_tags_are_escaped() {
+    assert_eq!(html_to_markup("<b>x <i"), "<b>x &lt;i</b>");
+}
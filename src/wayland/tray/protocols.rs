@@ -1,10 +1,13 @@
 use {
-    crate::wayland::{tray::TrayItemId, State},
+    crate::wayland::{scale::Logical, tray::TrayItemId, State},
     wayland_client::{
         protocol::{wl_seat::WlSeat, wl_surface::WlSurface},
         QueueHandle,
     },
-    wayland_protocols::xdg::shell::client::xdg_popup::XdgPopup,
+    wayland_protocols::xdg::shell::client::{
+        xdg_popup::XdgPopup,
+        xdg_positioner::{Anchor, Gravity},
+    },
 };
 
 pub mod ext_tray_v1 {
@@ -69,15 +72,241 @@ pub mod ext_tray_v1 {
             self.ack_configure(serial);
         }
 
-        fn get_popup(&self, popup: &XdgPopup, seat: &WlSeat, serial: u32) {
+        fn get_popup(&self, popup: &XdgPopup, seat: &WlSeat, serial: u32, _grab: bool) {
             self.get_popup(popup, seat, serial, KeyboardFocusHint::None);
         }
     }
 }
 
+pub mod wlr_layer_shell {
+    use {
+        crate::{
+            settings::{self, PanelEdge},
+            wayland::{
+                scale::Logical,
+                tray::{
+                    protocols::{ProtoName, TrayLayout, WaylandTray, WaylandTrayItem},
+                    TrayItemId,
+                },
+                utils::create_shm_buf_oneshot,
+                Singletons, State,
+            },
+        },
+        error_reporter::Report,
+        std::cell::Cell,
+        wayland_client::{
+            protocol::{
+                wl_buffer::WlBuffer, wl_output::WlOutput, wl_seat::WlSeat,
+                wl_subcompositor::WlSubcompositor, wl_subsurface::WlSubsurface,
+                wl_surface::WlSurface,
+            },
+            QueueHandle,
+        },
+        wayland_protocols::{
+            wp::viewporter::client::wp_viewport::WpViewport,
+            xdg::shell::client::{
+                xdg_popup::XdgPopup,
+                xdg_positioner::{Anchor, Gravity},
+            },
+        },
+        wayland_protocols_wlr::layer_shell::v1::client::{
+            zwlr_layer_shell_v1::{Layer, ZwlrLayerShellV1},
+            zwlr_layer_surface_v1::{self, ZwlrLayerSurfaceV1},
+        },
+    };
+
+    /// A panel drawn by us for compositors that do not implement ext-tray-v1.
+    ///
+    /// The panel is a layer surface attached to one edge of an output. Items are
+    /// subsurfaces of the panel.
+    pub struct LayerTray {
+        surface: WlSurface,
+        viewport: WpViewport,
+        layer_surface: ZwlrLayerSurfaceV1,
+        wl_subcompositor: WlSubcompositor,
+        background: Option<WlBuffer>,
+        edge: PanelEdge,
+        size: i32,
+        spacing: i32,
+        configured: Option<(i32, i32)>,
+    }
+
+    pub struct LayerTrayItem {
+        subsurface: WlSubsurface,
+        layer_surface: ZwlrLayerSurfaceV1,
+        position: Cell<(i32, i32)>,
+    }
+
+    impl LayerTray {
+        pub fn new(
+            s: &Singletons,
+            layer_shell: &ZwlrLayerShellV1,
+            wl_subcompositor: &WlSubcompositor,
+            output: &WlOutput,
+            name: u32,
+        ) -> Self {
+            let settings = settings::get();
+            let panel = &settings.panel;
+            let size = (panel.size * settings.scale).round().max(1.0) as i32;
+            let spacing = (panel.spacing * settings.scale).round().max(0.0) as i32;
+            let surface = s.wl_compositor.create_surface(&s.qh, ());
+            let viewport = s.wp_viewporter.get_viewport(&surface, &s.qh, ());
+            let layer_surface = layer_shell.get_layer_surface(
+                &surface,
+                Some(output),
+                Layer::Top,
+                "wl-tray-bridge".to_string(),
+                &s.qh,
+                name,
+            );
+            type A = zwlr_layer_surface_v1::Anchor;
+            let (anchor, width, height) = match panel.edge {
+                PanelEdge::Top => (A::Top | A::Left | A::Right, 0, size),
+                PanelEdge::Bottom => (A::Bottom | A::Left | A::Right, 0, size),
+                PanelEdge::Left => (A::Left | A::Top | A::Bottom, size, 0),
+                PanelEdge::Right => (A::Right | A::Top | A::Bottom, size, 0),
+            };
+            layer_surface.set_anchor(anchor);
+            layer_surface.set_size(width as u32, height as u32);
+            layer_surface.set_exclusive_zone(size);
+            surface.commit();
+            let c = &panel.background_color;
+            let pixel = [c.b, c.g, c.r]
+                .map(|v| (v * c.a * 255.0).round() as u8)
+                .into_iter()
+                .chain([(c.a * 255.0).round() as u8])
+                .collect::<Vec<_>>();
+            let background = match create_shm_buf_oneshot(s, &pixel, (1, 1)) {
+                Ok(b) => Some(b),
+                Err(e) => {
+                    log::error!("Could not create the panel background: {}", Report::new(e));
+                    None
+                }
+            };
+            Self {
+                surface,
+                viewport,
+                layer_surface,
+                wl_subcompositor: wl_subcompositor.clone(),
+                background,
+                edge: panel.edge,
+                size,
+                spacing,
+                configured: None,
+            }
+        }
+    }
+
+    impl Drop for LayerTray {
+        fn drop(&mut self) {
+            self.layer_surface.destroy();
+            self.viewport.destroy();
+            self.surface.destroy();
+            if let Some(b) = &self.background {
+                b.destroy();
+            }
+        }
+    }
+
+    impl WaylandTray for LayerTray {
+        fn proto_name(&self) -> ProtoName {
+            ProtoName::WlrLayerShell
+        }
+
+        fn get_tray_item(
+            &self,
+            surface: &WlSurface,
+            qh: &QueueHandle<State>,
+            _id: TrayItemId,
+        ) -> Box<dyn WaylandTrayItem> {
+            let subsurface = self
+                .wl_subcompositor
+                .get_subsurface(surface, &self.surface, qh, ());
+            subsurface.set_desync();
+            Box::new(LayerTrayItem {
+                subsurface,
+                layer_surface: self.layer_surface.clone(),
+                position: Cell::new((0, 0)),
+            })
+        }
+
+        fn handle_configure(&mut self, serial: u32, width: u32, height: u32) {
+            self.layer_surface.ack_configure(serial);
+            let width = (width as i32).max(self.size);
+            let height = (height as i32).max(self.size);
+            self.configured = Some((width, height));
+            self.viewport.set_destination(width, height);
+            self.surface.attach(self.background.as_ref(), 0, 0);
+            self.surface.damage_buffer(0, 0, i32::MAX, i32::MAX);
+            self.surface.commit();
+        }
+
+        fn arrange(&mut self, items: &[&dyn WaylandTrayItem]) -> Option<TrayLayout> {
+            let (width, height) = self.configured?;
+            let horizontal = matches!(self.edge, PanelEdge::Top | PanelEdge::Bottom);
+            let mut pos = self.spacing;
+            for item in items {
+                let position = match horizontal {
+                    true => (pos, (height - self.size) / 2),
+                    false => ((width - self.size) / 2, pos),
+                };
+                item.set_position(position.0, position.1);
+                pos += self.size + self.spacing;
+            }
+            self.surface.commit();
+            let (anchor, gravity) = match self.edge {
+                PanelEdge::Top => (Anchor::Bottom, Gravity::Bottom),
+                PanelEdge::Bottom => (Anchor::Top, Gravity::Top),
+                PanelEdge::Left => (Anchor::Right, Gravity::Right),
+                PanelEdge::Right => (Anchor::Left, Gravity::Left),
+            };
+            Some(TrayLayout {
+                size: Logical(self.size, self.size),
+                anchor,
+                gravity,
+            })
+        }
+    }
+
+    impl WaylandTrayItem for LayerTrayItem {
+        fn destroy(&self) {
+            self.subsurface.destroy();
+        }
+
+        fn ack_configure(&self, _serial: u32) {
+            // nothing
+        }
+
+        fn get_popup(&self, popup: &XdgPopup, seat: &WlSeat, serial: u32, grab: bool) {
+            self.layer_surface.get_popup(popup);
+            if grab {
+                popup.grab(seat, serial);
+            }
+        }
+
+        fn set_position(&self, x: i32, y: i32) {
+            self.position.set((x, y));
+            self.subsurface.set_position(x, y);
+        }
+
+        fn popup_origin(&self) -> (i32, i32) {
+            self.position.get()
+        }
+    }
+}
+
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum ProtoName {
     ExtTrayV1,
+    WlrLayerShell,
+}
+
+/// The configuration of items in trays that position items themselves.
+#[derive(Copy, Clone, Debug)]
+pub struct TrayLayout {
+    pub size: Logical,
+    pub anchor: Anchor,
+    pub gravity: Gravity,
 }
 
 pub trait WaylandTray {
@@ -88,10 +317,32 @@ pub trait WaylandTray {
         qh: &QueueHandle<State>,
         id: TrayItemId,
     ) -> Box<dyn WaylandTrayItem>;
+
+    /// Handles a configure event of the tray's own surface.
+    fn handle_configure(&mut self, serial: u32, width: u32, height: u32) {
+        let _ = (serial, width, height);
+    }
+
+    /// Positions the items in the given order.
+    ///
+    /// Returns `None` if the items are positioned by the compositor.
+    fn arrange(&mut self, items: &[&dyn WaylandTrayItem]) -> Option<TrayLayout> {
+        let _ = items;
+        None
+    }
 }
 
 pub trait WaylandTrayItem {
     fn destroy(&self);
     fn ack_configure(&self, serial: u32);
-    fn get_popup(&self, popup: &XdgPopup, seat: &WlSeat, serial: u32);
+    fn get_popup(&self, popup: &XdgPopup, seat: &WlSeat, serial: u32, grab: bool);
+
+    fn set_position(&self, x: i32, y: i32) {
+        let _ = (x, y);
+    }
+
+    /// Returns the position of the item relative to the parent of its popups.
+    fn popup_origin(&self) -> (i32, i32) {
+        (0, 0)
+    }
 }
@@ -37,35 +37,3 @@ pub fn create_shm_buf(
     pool.destroy();
     Ok((buffer, memfd))
 }
-//
-// pub struct IconTick {
-//     send: UnboundedSender<TrayItemId>,
-// }
-//
-// impl IconTick {
-//     pub fn new(sink: &EventSink) -> Self {
-//         let sink = sink.clone();
-//         let (send, mut recv) = unbounded_channel();
-//         tokio::spawn(async move {
-//             let mut interval = interval(Duration::from_secs(1));
-//             interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
-//             loop {
-//                 let Some(first) = recv.recv().await else {
-//                     return;
-//                 };
-//                 interval.tick().await;
-//                 let mut elements = AHashSet::new();
-//                 elements.insert(first);
-//                 while let Ok(e) = recv.try_recv() {
-//                     elements.insert(e);
-//                 }
-//                 sink.send(move |state| state.handle_tick(elements));
-//             }
-//         });
-//         Self { send }
-//     }
-//
-//     pub fn request_tick(&self, id: TrayItemId) {
-//         let _ = self.send.send(id);
-//     }
-// }